@@ -27,7 +27,7 @@ fn main() {
             use rug::Float;
 
             use mandelbrot::{
-                bounded::BoundsSettings,
+                bounded::{BailoutNorm, BoundsSettings},
                 compute::{Compute, ComputeEngine, ComputeSettings},
             };
 
@@ -43,7 +43,7 @@ fn main() {
                 size.0,
                 size.1,
                 ComputeEngine::MPC,
-                BoundsSettings::new(250, precision),
+                BoundsSettings::new(250, precision, 1e-6, 2.0, BailoutNorm::Modulus),
             );
 
             Compute::compute_set(None, None, &settings);
@@ -51,6 +51,14 @@ fn main() {
             let duration = std::time::Instant::now() - start;
             println!("{}", duration.as_secs_f64());
         }
+        "formula" => match AppSettings::new().with_expression(&args.next().unwrap_or_default()) {
+            Ok(settings) => App::new(settings).run(),
+            Err(error) => eprintln!("invalid formula: {}", error),
+        },
+        "newton" => match AppSettings::new().with_polynomial(&args.next().unwrap_or_default()) {
+            Ok(settings) => App::new(settings).run(),
+            Err(error) => eprintln!("invalid polynomial: {}", error),
+        },
         _ => {
             let mut app = App::new(AppSettings::new());
             app.run();
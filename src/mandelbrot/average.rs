@@ -0,0 +1,152 @@
+use mandelbrot::bounded::BoundsSettings;
+
+/// Mean of a series that remembers its last term, so the means with and
+/// without it can be blended.
+#[derive(Clone, Copy)]
+struct Average {
+    sum: f64,
+    last: f64,
+    count: u64,
+}
+
+impl Average {
+    fn new() -> Average {
+        Average {
+            sum: 0.0,
+            last: 0.0,
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.sum += value;
+        self.last = value;
+        self.count += 1;
+    }
+
+    fn interpolate(&self, fraction: f64) -> f32 {
+        match self.count {
+            0 => 0.0,
+            1 => self.sum as f32,
+            count => {
+                let previous = (self.sum - self.last) / (count - 1) as f64;
+                let current = self.sum / count as f64;
+                (previous + (current - previous) * fraction) as f32
+            }
+        }
+    }
+}
+
+/// Stripe average `(1 + sin(s arg z)) / 2` and triangle inequality average
+/// `(|z| - |m - |c||) / (m + |c| - |m - |c||)` with `m = |z - c|` over an
+/// orbit, accumulated only when the settings ask for them.
+#[derive(Clone, Copy)]
+pub struct OrbitAverages {
+    density: Option<f64>,
+    stripe: Average,
+    triangle: Average,
+}
+
+impl OrbitAverages {
+    pub fn new(settings: BoundsSettings) -> OrbitAverages {
+        OrbitAverages {
+            density: settings.stripe_density,
+            stripe: Average::new(),
+            triangle: Average::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.density.is_some()
+    }
+
+    /// Adds the orbit value `z` of the iteration that added `c`.
+    pub fn update(&mut self, z: (f64, f64), c: (f64, f64)) {
+        if let Some(density) = self.density {
+            self.stripe
+                .add(0.5 + 0.5 * (density * z.1.atan2(z.0)).sin());
+            let m = (z.0 - c.0).hypot(z.1 - c.1);
+            let c = c.0.hypot(c.1);
+            let low = (m - c).abs();
+            let high = m + c;
+            // The first iteration, where m = 0, gives no bounds.
+            if high > low {
+                self.triangle.add((z.0.hypot(z.1) - low) / (high - low));
+            }
+        }
+    }
+
+    /// Both averages, blended between the orbit with and without its last
+    /// value by the fractional part of the smooth iteration count.
+    pub fn finish(&self, fraction: f64) -> (f32, f32) {
+        (
+            self.stripe.interpolate(fraction),
+            self.triangle.interpolate(fraction),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OrbitAverages;
+    use mandelbrot::bounded::{BailoutNorm, BoundsSettings};
+
+    fn averages() -> OrbitAverages {
+        let settings = BoundsSettings::new(100, 53, 0.0, 2.0, BailoutNorm::Modulus)
+            .with_stripe_density(Some(1.0));
+        OrbitAverages::new(settings)
+    }
+
+    #[test]
+    fn skips_the_first_iteration_in_the_triangle_average() {
+        let c = (0.0, 0.5);
+        let mut averages = averages();
+        averages.update(c, c);
+        assert_eq!(averages.finish(1.0).1, 0.0);
+        // `|z| = sqrt(5) / 4` between the bounds `|m - |c|| = 1/4` and
+        // `m + |c| = 3/4`.
+        averages.update((-0.25, 0.5), c);
+        let expected = (5f64.sqrt() - 1.0) / 2.0;
+        assert!((f64::from(averages.finish(1.0).1) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn averages_stay_in_the_unit_interval() {
+        for &c in &[
+            (0.3, 0.6),
+            (-0.75, 0.1),
+            (-2.0, 0.0),
+            (0.26, 0.0),
+            (-0.1, 1.2),
+        ] {
+            let mut averages = averages();
+            let mut z = (0.0, 0.0);
+            for _ in 0..1000 {
+                z = (z.0 * z.0 - z.1 * z.1 + c.0, 2.0 * z.0 * z.1 + c.1);
+                if z.0 * z.0 + z.1 * z.1 > 1e6 {
+                    break;
+                }
+                averages.update(z, c);
+            }
+            for fraction in 0..=10 {
+                let (stripe, triangle) = averages.finish(f64::from(fraction) / 10.0);
+                assert!((0.0..=1.0).contains(&stripe), "{:?} {}", c, stripe);
+                assert!((0.0..=1.0).contains(&triangle), "{:?} {}", c, triangle);
+            }
+        }
+    }
+
+    // Stripe values 0.5, 1 and 0 at the arguments 0, pi/2 and -pi/2: the
+    // fraction moves from the mean without the last value to the full mean.
+    #[test]
+    fn interpolates_with_the_smooth_fraction() {
+        let mut averages = averages();
+        for &z in &[(1.0, 0.0), (0.0, 1.0), (0.0, -1.0)] {
+            averages.update(z, (0.0, 0.0));
+        }
+        let stripe = |fraction| f64::from(averages.finish(fraction).0);
+        assert!((stripe(0.0) - 0.75).abs() < 1e-6);
+        assert!((stripe(1.0) - 0.5).abs() < 1e-6);
+        assert!((stripe(0.25) - 0.6875).abs() < 1e-6);
+    }
+}
@@ -1,62 +1,546 @@
-use packed_simd::{f64x4, u64x4};
+use packed_simd::{f64x4, m64x4, u64x4};
 use rug::{Assign, Complex, Float};
 
+use mandelbrot::average::OrbitAverages;
+use mandelbrot::compute::{ComputeSettings, Plane};
+use mandelbrot::floatexp::FloatExp;
+use mandelbrot::formula::{Arithmetic, Formula};
+use mandelbrot::interior::Interior;
+use mandelbrot::layer::Layer;
+use mandelbrot::multidouble::{DoubleDouble, DoubleDoublex4, QuadDouble};
+use mandelbrot::perturbation::Delta;
+use mandelbrot::trap::{Trap, TrapDistance};
+
 #[derive(Debug, Copy, Clone)]
 pub enum Bound {
-    Bounded,
-    Unbounded(u64),
+    Bounded(Option<Interior>),
+    Unbounded(Escape),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Escape {
+    pub iter: u64,
+    pub smooth: f32,
+    pub distance: f32,
+    pub z: (f32, f32),
+    pub derivative: (f32, f32),
+}
+
+impl Escape {
+    /// `norm` is the squared bailout norm of the first value past the escape
+    /// radius, the normalized count is continuous in `(iter, iter + 1]` for a
+    /// formula of the given `degree`.
+    /// `derivative` is `dz/dc` scaled by the pixel spacing, which gives the
+    /// exterior distance estimate `|z| ln|z| / |dz/dc|` in pixels.
+    pub fn new(
+        iter: u64,
+        norm: f64,
+        z: (f64, f64),
+        derivative: (f64, f64),
+        degree: f64,
+        settings: BoundsSettings,
+    ) -> Escape {
+        let ratio = norm.ln() / (2.0 * settings.escape_radius.ln());
+        let modulus = z.0.hypot(z.1);
+        Escape {
+            iter,
+            smooth: (iter as f64 + 1.0 - ratio.ln() / degree.ln()) as f32,
+            distance: (modulus * modulus.ln() / derivative.0.hypot(derivative.1)) as f32,
+            z: (z.0 as f32, z.1 as f32),
+            derivative: (derivative.0 as f32, derivative.1 as f32),
+        }
+    }
+}
+
+/// Output of `check_bounded` for one pixel. `trap` is the smallest distance of
+/// the orbit to the orbit trap, infinite when there is none. `stripe` and
+/// `triangle` are the orbit averages of escaping pixels, zero otherwise.
+#[derive(Debug, Copy, Clone)]
+pub struct Pixel {
+    pub bound: Bound,
+    pub trap: f32,
+    pub stripe: f32,
+    pub triangle: f32,
+}
+
+impl Pixel {
+    pub fn new(bound: Bound, trap: f64) -> Pixel {
+        Pixel {
+            bound,
+            trap: trap as f32,
+            stripe: 0.0,
+            triangle: 0.0,
+        }
+    }
+
+    /// Fills in the averages, interpolated with the smooth iteration count.
+    pub fn with_averages(mut self, averages: &OrbitAverages) -> Pixel {
+        if let (Bound::Unbounded(escape), true) = (self.bound, averages.is_enabled()) {
+            let fraction = f64::from(escape.smooth) - escape.iter as f64;
+            let (stripe, triangle) = averages.finish(fraction);
+            self.stripe = stripe;
+            self.triangle = triangle;
+        }
+        self
+    }
 }
 
 pub trait BoundsChecker: Send {
-    fn check_bounded(x: &[Float], y: &[Float], settings: BoundsSettings, out: &mut [Bound]);
+    type Context: Send + Sync + 'static;
+
+    fn prepare(settings: &ComputeSettings) -> Self::Context;
+    fn check_bounded<F: Formula>(
+        context: &Self::Context,
+        formula: F,
+        x: &[Float],
+        y: &[Float],
+        step: &Float,
+        settings: BoundsSettings,
+        out: &mut [Pixel],
+    );
     fn mask() -> Vec<usize>;
+
+    /// Layers of the `ComputedSet` this engine fills in. The trap and the
+    /// averages are left out when the settings do not ask for them.
+    fn layers() -> Vec<Layer> {
+        Layer::escape_time()
+    }
+
+    fn glitches(_context: &Self::Context) -> u64 {
+        0
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum BailoutNorm {
+    Modulus,
+    Max,
+    Real,
+    Imaginary,
+    Sum,
+}
+
+impl BailoutNorm {
+    pub fn to_int(self) -> i32 {
+        match self {
+            Self::Modulus => 0,
+            Self::Max => 1,
+            Self::Real => 2,
+            Self::Imaginary => 3,
+            Self::Sum => 4,
+        }
+    }
+
+    pub fn from_int(value: i32) -> Self {
+        match value {
+            0 => Self::Modulus,
+            1 => Self::Max,
+            2 => Self::Real,
+            3 => Self::Imaginary,
+            4 => Self::Sum,
+            _ => Self::Modulus,
+        }
+    }
+
+    /// Squared norm of `z`, compared against the squared escape radius.
+    pub fn apply(self, re: f64, im: f64) -> f64 {
+        match self {
+            Self::Modulus => re * re + im * im,
+            Self::Max => (re * re).max(im * im),
+            Self::Real => re * re,
+            Self::Imaginary => im * im,
+            Self::Sum => (re + im) * (re + im),
+        }
+    }
+
+    pub fn apply_x4(self, re: f64x4, im: f64x4) -> f64x4 {
+        match self {
+            Self::Modulus => re * re + im * im,
+            Self::Max => (re * re).max(im * im),
+            Self::Real => re * re,
+            Self::Imaginary => im * im,
+            Self::Sum => (re + im) * (re + im),
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
 pub struct BoundsSettings {
     pub limit: u64,
     pub precision: u32,
+    pub series_tolerance: f64,
+    pub escape_radius: f64,
+    pub bailout_norm: BailoutNorm,
+    pub trap: Option<Trap>,
+    /// Stripe density of the orbit averages, which are skipped when `None`.
+    pub stripe_density: Option<f64>,
 }
 
 impl BoundsSettings {
-    pub fn new(limit: u64, precision: u32) -> BoundsSettings {
-        BoundsSettings { limit, precision }
+    pub fn new(
+        limit: u64,
+        precision: u32,
+        series_tolerance: f64,
+        escape_radius: f64,
+        bailout_norm: BailoutNorm,
+    ) -> BoundsSettings {
+        BoundsSettings {
+            limit,
+            precision,
+            series_tolerance,
+            escape_radius,
+            bailout_norm,
+            trap: None,
+            stripe_density: None,
+        }
+    }
+
+    pub fn with_trap(mut self, trap: Option<Trap>) -> BoundsSettings {
+        self.trap = trap;
+        self
+    }
+
+    pub fn with_stripe_density(mut self, stripe_density: Option<f64>) -> BoundsSettings {
+        self.stripe_density = stripe_density;
+        self
+    }
+
+    pub fn bailout(&self) -> f64 {
+        self.escape_radius * self.escape_radius
+    }
+}
+
+pub trait Real: Arithmetic {
+    fn from_float(value: &Float) -> Self;
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+
+impl Real for f32 {
+    fn from_float(value: &Float) -> Self {
+        value.to_f32()
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self)
     }
 }
 
-macro_rules! float_to {
-    ($e:expr, f32) => {
-        $e.to_f32()
+impl Real for f64 {
+    fn from_float(value: &Float) -> Self {
+        value.to_f64()
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+pub trait SimdReal: Arithmetic {
+    fn from_floats(values: &[Float]) -> Self;
+    fn splat(value: f64) -> Self;
+    fn to_f64x4(self) -> f64x4;
+}
+
+impl SimdReal for f64x4 {
+    fn from_floats(values: &[Float]) -> Self {
+        f64x4::from_slice_unaligned(
+            values
+                .iter()
+                .map(|x| x.to_f64())
+                .collect::<Vec<_>>()
+                .as_slice(),
+        )
+    }
+
+    fn splat(value: f64) -> Self {
+        f64x4::splat(value)
+    }
+
+    fn to_f64x4(self) -> f64x4 {
+        self
+    }
+}
+
+// Orbits closer than this fraction of a pixel to an earlier value are taken
+// to have reached an attracting cycle.
+pub const PERIOD_TOLERANCE: f64 = 1e-3;
+// Rounding margin on the cardioid and bulb tests, points closer than this to
+// their boundary are iterated normally.
+const COMPONENT_MARGIN: f64 = 1e-12;
+
+/// Cheap rejection of points inside the main cardioid or the period 2 bulb,
+/// returning the period of the component.
+pub fn main_component(x: f64, y: f64) -> Option<u64> {
+    let xq = x - 0.25;
+    let q = xq * xq + y * y;
+    if q * (q + xq) < 0.25 * y * y - COMPONENT_MARGIN {
+        Some(1)
+    } else if (x + 1.0) * (x + 1.0) + y * y < 0.0625 - COMPONENT_MARGIN {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Brent-style schedule for periodicity checking: the orbit is compared to a
+/// saved value which is refreshed after windows of doubling length.
+pub struct Periodicity {
+    count: u64,
+    window: u64,
+}
+
+impl Periodicity {
+    pub fn new() -> Periodicity {
+        Periodicity {
+            count: 0,
+            window: 1,
+        }
+    }
+
+    /// Number of iterations between the saved value and the current one.
+    pub fn period(&self) -> u64 {
+        self.count + 1
+    }
+
+    /// Returns `true` when the current value should become the saved one.
+    pub fn advance(&mut self) -> bool {
+        self.count += 1;
+        if self.count == self.window {
+            self.count = 0;
+            self.window *= 2;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn check_bounded_real<R: Real, F: Formula>(
+    x: &Float,
+    y: &Float,
+    step: f64,
+    plane: &Plane,
+    formula: F,
+    settings: BoundsSettings,
+) -> Pixel {
+    let pixel = (R::from_float(x), R::from_float(y));
+    let mut trap = TrapDistance::new(settings.trap);
+    let mut averages = OrbitAverages::new(settings);
+    // The derivative is dz/dc in the Mandelbrot plane and dz/dz0 in a Julia
+    // plane, which only differ in their start value and the added constant.
+    let (mut z, c, cf, mut dz, offset) = match plane {
+        Plane::Mandelbrot => {
+            let cf = (x.to_f64(), y.to_f64());
+            // The shortcut skips the orbit the trap is measured on.
+            if let (Some(period), None) = (formula.main_component(cf), settings.trap) {
+                return trap.pixel(Bound::Bounded(formula.analyze(
+                    cf,
+                    (0.0, 0.0),
+                    period,
+                    step,
+                    plane,
+                )));
+            }
+            let zero = R::from_f64(0.0);
+            ((zero, zero), pixel, cf, (0.0, 0.0), 1.0)
+        }
+        Plane::Julia(cx, cy) => (
+            pixel,
+            (R::from_float(cx), R::from_float(cy)),
+            (cx.to_f64(), cy.to_f64()),
+            (1.0, 0.0),
+            0.0,
+        ),
     };
-    ($e:expr, f64) => {
-        $e.to_f64()
+    let bailout = settings.bailout();
+    let epsilon = step * PERIOD_TOLERANCE;
+    let mut zf = (z.0.to_f64(), z.1.to_f64());
+    let mut saved = z;
+    let mut periodicity = Periodicity::new();
+    let mut iter = 0;
+    while iter < settings.limit {
+        dz = formula.derivative(zf, dz);
+        dz.0 += offset;
+        z = formula.apply(z);
+        z = (z.0 + c.0, z.1 + c.1);
+        zf = (z.0.to_f64(), z.1.to_f64());
+        trap.update(zf);
+        averages.update(zf, cf);
+        let norm = settings.bailout_norm.apply(zf.0, zf.1);
+        if norm < bailout {
+            iter += 1;
+        } else {
+            let derivative = (dz.0 * step, dz.1 * step);
+            let degree = formula.degree();
+            let escape = Escape::new(iter, norm, zf, derivative, degree, settings);
+            return trap
+                .pixel(Bound::Unbounded(escape))
+                .with_averages(&averages);
+        }
+        if (z.0 - saved.0).to_f64().abs() < epsilon && (z.1 - saved.1).to_f64().abs() < epsilon {
+            let period = periodicity.period();
+            return trap.pixel(Bound::Bounded(formula.analyze(cf, zf, period, step, plane)));
+        }
+        if periodicity.advance() {
+            saved = z;
+        }
+    }
+    trap.pixel(Bound::Bounded(None))
+}
+
+fn check_bounded_simd<R: SimdReal, F: Formula>(
+    x: &[Float],
+    y: &[Float],
+    step: f64,
+    plane: &Plane,
+    formula: F,
+    settings: BoundsSettings,
+    out: &mut [Pixel],
+) {
+    let pixel = (R::from_floats(x), R::from_floats(y));
+    let (mut z, c, mut dz, offset) = match plane {
+        Plane::Mandelbrot => (
+            (R::splat(0.0), R::splat(0.0)),
+            pixel,
+            (f64x4::splat(0.0), f64x4::splat(0.0)),
+            f64x4::splat(1.0),
+        ),
+        Plane::Julia(cx, cy) => (
+            pixel,
+            (
+                R::from_floats(&vec![cx.clone(); x.len()]),
+                R::from_floats(&vec![cy.clone(); y.len()]),
+            ),
+            (f64x4::splat(1.0), f64x4::splat(0.0)),
+            f64x4::splat(0.0),
+        ),
     };
+    let mut zf = (z.0.to_f64x4(), z.1.to_f64x4());
+    let mut iter = u64x4::splat(0);
+    let mut escaped = [f64x4::splat(0.0); 5];
+    let mut bounded = m64x4::splat(false);
+    let mut periods = u64x4::splat(0);
+    let mut cycle = (f64x4::splat(0.0), f64x4::splat(0.0));
+    let mut traps = f64x4::splat(std::f64::INFINITY);
+    let mut averages = [OrbitAverages::new(settings); 4];
+    let cf = (c.0.to_f64x4(), c.1.to_f64x4());
+    if let (Plane::Mandelbrot, None) = (plane, settings.trap) {
+        for (i, (x, y)) in x.iter().zip(y.iter()).enumerate() {
+            if let Some(period) = formula.main_component((x.to_f64(), y.to_f64())) {
+                bounded = bounded.replace(i, true);
+                periods = periods.replace(i, period);
+            }
+        }
+    }
+    let mut active = !bounded;
+    let mut saved = z;
+    let mut periodicity = Periodicity::new();
+
+    let check = f64x4::splat(settings.bailout());
+    let epsilon = f64x4::splat(step * PERIOD_TOLERANCE);
+    for _ in 0..settings.limit {
+        dz = formula.derivative(zf, dz);
+        dz.0 += offset;
+        z = formula.apply(z);
+        z = (z.0 + c.0, z.1 + c.1);
+        zf = (z.0.to_f64x4(), z.1.to_f64x4());
+        if let Some(trap) = settings.trap {
+            traps = active.select(traps.min(trap.distance_x4(zf.0, zf.1)), traps);
+        }
+        if settings.stripe_density.is_some() {
+            for (i, averages) in averages.iter_mut().enumerate() {
+                if active.extract(i) {
+                    averages.update(
+                        (zf.0.extract(i), zf.1.extract(i)),
+                        (cf.0.extract(i), cf.1.extract(i)),
+                    );
+                }
+            }
+        }
+        let norm = settings.bailout_norm.apply_x4(zf.0, zf.1);
+        let mask = norm.lt(check);
+        let escaping = active & !mask;
+        for (escaped, value) in escaped.iter_mut().zip(&[norm, zf.0, zf.1, dz.0, dz.1]) {
+            *escaped = escaping.select(*value, *escaped);
+        }
+        let periodic = (z.0 - saved.0).to_f64x4().abs().lt(epsilon)
+            & (z.1 - saved.1).to_f64x4().abs().lt(epsilon);
+        let found = active & mask & periodic;
+        bounded |= found;
+        periods = found.select(u64x4::splat(periodicity.period()), periods);
+        cycle = (found.select(zf.0, cycle.0), found.select(zf.1, cycle.1));
+        active = active & mask & !periodic;
+        if active.none() {
+            break;
+        }
+        iter = active.select(iter + u64x4::splat(1), iter);
+        if periodicity.advance() {
+            saved = z;
+        }
+    }
+    let mut checks = vec![0; 4];
+    iter.write_to_slice_unaligned(&mut checks);
+    let [norm, zr, zi, dzr, dzi] = escaped;
+    for (i, (o, n)) in out.iter_mut().zip(checks.iter()).enumerate() {
+        let bound = if bounded.extract(i) {
+            Bound::Bounded(formula.analyze(
+                (cf.0.extract(i), cf.1.extract(i)),
+                (cycle.0.extract(i), cycle.1.extract(i)),
+                periods.extract(i),
+                step,
+                plane,
+            ))
+        } else if *n < settings.limit {
+            Bound::Unbounded(Escape::new(
+                *n,
+                norm.extract(i),
+                (zr.extract(i), zi.extract(i)),
+                (dzr.extract(i) * step, dzi.extract(i) * step),
+                formula.degree(),
+                settings,
+            ))
+        } else {
+            Bound::Bounded(None)
+        };
+        *o = Pixel::new(bound, traps.extract(i)).with_averages(&averages[i]);
+    }
 }
 
-macro_rules! impl_boundscheck_primitive {
+macro_rules! impl_boundscheck_real {
     ($type:tt) => {
         impl BoundsChecker for $type {
-            fn check_bounded(
+            type Context = Plane;
+
+            fn prepare(settings: &ComputeSettings) -> Plane {
+                settings.get_plane().clone()
+            }
+
+            fn check_bounded<F: Formula>(
+                plane: &Plane,
+                formula: F,
                 x: &[Float],
                 y: &[Float],
+                step: &Float,
                 settings: BoundsSettings,
-                out: &mut [Bound],
+                out: &mut [Pixel],
             ) {
-                let x = float_to!(&x[0], $type);
-                let y = float_to!(&y[0], $type);
-                let c = (x, y);
-                let mut z = (0.0, 0.0);
-                let mut iter = 0;
-                while iter < settings.limit {
-                    z = (z.0 * z.0 - z.1 * z.1 + c.0, 2.0 * z.0 * z.1 + c.1);
-                    if z.0 * z.0 + z.1 * z.1 < 4.0 {
-                        iter += 1;
-                    } else {
-                        out[0] = Bound::Unbounded(iter);
-                        return;
-                    }
-                }
-                out[0] = Bound::Bounded;
+                out[0] = check_bounded_real::<$type, F>(
+                    &x[0],
+                    &y[0],
+                    step.to_f64(),
+                    plane,
+                    formula,
+                    settings,
+                );
             }
 
             fn mask() -> Vec<usize> {
@@ -66,27 +550,144 @@ macro_rules! impl_boundscheck_primitive {
     };
 }
 
-impl_boundscheck_primitive!(f64);
-impl_boundscheck_primitive!(f32);
+impl_boundscheck_real!(f64);
+impl_boundscheck_real!(f32);
+impl_boundscheck_real!(DoubleDouble);
+impl_boundscheck_real!(QuadDouble);
+
+macro_rules! impl_boundscheck_simd {
+    ($type:tt) => {
+        impl BoundsChecker for $type {
+            type Context = Plane;
+
+            fn prepare(settings: &ComputeSettings) -> Plane {
+                settings.get_plane().clone()
+            }
+
+            fn check_bounded<F: Formula>(
+                plane: &Plane,
+                formula: F,
+                x: &[Float],
+                y: &[Float],
+                step: &Float,
+                settings: BoundsSettings,
+                out: &mut [Pixel],
+            ) {
+                check_bounded_simd::<$type, F>(x, y, step.to_f64(), plane, formula, settings, out);
+            }
+
+            fn mask() -> Vec<usize> {
+                vec![0, 1, 2, 3]
+            }
+        }
+    };
+}
+
+impl_boundscheck_simd!(f64x4);
+impl_boundscheck_simd!(DoubleDoublex4);
 
 impl BoundsChecker for Complex {
-    fn check_bounded(x: &[Float], y: &[Float], settings: BoundsSettings, out: &mut [Bound]) {
-        let mut buffer = Complex::new(settings.precision);
-        let c = Complex::with_val(settings.precision, (&x[0], &y[0]));
-        let mut z = Complex::with_val(settings.precision, (0.0, 0.0));
+    type Context = Plane;
+
+    fn prepare(settings: &ComputeSettings) -> Plane {
+        settings.get_plane().clone()
+    }
+
+    fn check_bounded<F: Formula>(
+        plane: &Plane,
+        formula: F,
+        x: &[Float],
+        y: &[Float],
+        step: &Float,
+        settings: BoundsSettings,
+        out: &mut [Pixel],
+    ) {
+        let pixel = Complex::with_val(settings.precision, (&x[0], &y[0]));
+        let mut trap = TrapDistance::new(settings.trap);
+        let (mut z, c, offset) = match plane {
+            Plane::Mandelbrot => {
+                let cf = (x[0].to_f64(), y[0].to_f64());
+                if let (Some(period), None) = (formula.main_component(cf), settings.trap) {
+                    out[0] = trap.pixel(Bound::Bounded(formula.analyze(
+                        cf,
+                        (0.0, 0.0),
+                        period,
+                        step.to_f64(),
+                        plane,
+                    )));
+                    return;
+                }
+                (Complex::new(settings.precision), pixel, 1.0)
+            }
+            Plane::Julia(cx, cy) => (pixel, Complex::with_val(settings.precision, (cx, cy)), 0.0),
+        };
+        let cf = (c.real().to_f64(), c.imag().to_f64());
+        let mut averages = OrbitAverages::new(settings);
+        let mut saved = z.clone();
+        let mut difference = (
+            Float::new(settings.precision),
+            Float::new(settings.precision),
+        );
+        let mut periodicity = Periodicity::new();
+        let epsilon = FloatExp::from_float(step) * FloatExp::from_f64(PERIOD_TOLERANCE);
+        // The derivative grows like 1 / step, so it is kept in FloatExp to
+        // stay representable at any zoom MPC can reach.
+        let offset = FloatExp::from_f64(offset);
+        let mut zf = (
+            FloatExp::from_float(z.real()),
+            FloatExp::from_float(z.imag()),
+        );
+        let mut dz = (FloatExp::from_f64(1.0) - offset, FloatExp::from_f64(0.0));
+        let bailout = settings.bailout();
         let mut iter = 0;
         while iter < settings.limit {
-            let z_temp = Complex::with_val(settings.precision, z.square_ref());
-            z.assign(z_temp + &c);
-            buffer.assign(z.norm_ref());
-            if buffer.real() < &4 {
+            dz = formula.derivative(zf, dz);
+            dz.0 = dz.0 + offset;
+            formula.apply_mpc(&mut z);
+            z += &c;
+            zf = (
+                FloatExp::from_float(z.real()),
+                FloatExp::from_float(z.imag()),
+            );
+            let zz = (z.real().to_f64(), z.imag().to_f64());
+            trap.update(zz);
+            averages.update(zz, cf);
+            let norm = settings.bailout_norm.apply(zz.0, zz.1);
+            if norm < bailout {
                 iter += 1;
             } else {
-                out[0] = Bound::Unbounded(iter);
+                let step = FloatExp::from_float(step);
+                out[0] = trap
+                    .pixel(Bound::Unbounded(Escape::new(
+                        iter,
+                        norm,
+                        zz,
+                        ((dz.0 * step).to_f64(), (dz.1 * step).to_f64()),
+                        formula.degree(),
+                        settings,
+                    )))
+                    .with_averages(&averages);
                 return;
             }
+            difference.0.assign(z.real() - saved.real());
+            difference.1.assign(z.imag() - saved.imag());
+            if FloatExp::from_float(&difference.0).abs() < epsilon
+                && FloatExp::from_float(&difference.1).abs() < epsilon
+            {
+                out[0] = trap.pixel(Bound::Bounded(formula.analyze(
+                    cf,
+                    zz,
+                    periodicity.period(),
+                    step.to_f64(),
+                    plane,
+                )));
+                return;
+            }
+            if periodicity.advance() {
+                saved.assign(&z);
+            }
         }
-        out[0] = Bound::Bounded;
+        out[0] = trap.pixel(Bound::Bounded(None));
     }
 
     fn mask() -> Vec<usize> {
@@ -94,42 +695,140 @@ impl BoundsChecker for Complex {
     }
 }
 
-impl BoundsChecker for f64x4 {
-    fn check_bounded(x: &[Float], y: &[Float], settings: BoundsSettings, out: &mut [Bound]) {
-        let x = f64x4::from_slice_unaligned(
-            x.iter().map(|x| x.to_f64()).collect::<Vec<_>>().as_slice(),
-        );
-        let y = f64x4::from_slice_unaligned(
-            y.iter().map(|y| y.to_f64()).collect::<Vec<_>>().as_slice(),
-        );
-        let c = (x, y);
-        let mut z = (f64x4::splat(0.0), f64x4::splat(0.0));
-        let mut iter = u64x4::splat(0);
-
-        let check = f64x4::splat(4.0);
-        for _ in 0..settings.limit {
-            z = (
-                z.0 * z.0 - z.1 * z.1 + c.0,
-                f64x4::splat(2.0) * z.0 * z.1 + c.1,
-            );
-            let mask = (z.0 * z.0 + z.1 * z.1).lt(check);
-            if mask.none() {
-                break;
-            }
-            iter = mask.select(iter + u64x4::splat(1), iter);
+#[cfg(test)]
+mod tests {
+    use rug::Float;
+
+    use super::{
+        check_bounded_real, main_component, BailoutNorm, Bound, BoundsSettings, Escape, Periodicity,
+    };
+    use mandelbrot::compute::Plane;
+    use mandelbrot::formula::Mandelbrot;
+
+    // Escape of the pixel `c` in f64, with pixels `step` apart.
+    fn escape(c: (f64, f64), step: f64, settings: BoundsSettings) -> Escape {
+        let (x, y) = (Float::with_val(53, c.0), Float::with_val(53, c.1));
+        let pixel =
+            check_bounded_real::<f64, _>(&x, &y, step, &Plane::Mandelbrot, Mandelbrot, settings);
+        match pixel.bound {
+            Bound::Unbounded(escape) => escape,
+            Bound::Bounded(_) => panic!("{:?} does not escape", c),
         }
-        let mut checks = vec![0; 4];
-        iter.write_to_slice_unaligned(&mut checks);
-        for (o, n) in out.iter_mut().zip(checks.iter()) {
-            *o = if *n < settings.limit {
-                Bound::Unbounded(*n)
-            } else {
-                Bound::Bounded
+    }
+
+    // Along the real axis right of the cusp at 1/4 the integer count drops
+    // band by band, the normalized count has no jumps there.
+    #[test]
+    fn smooth_iterations_are_continuous_across_bands() {
+        let settings = BoundsSettings::new(1000, 53, 0.0, 1000.0, BailoutNorm::Modulus);
+        let mut last = escape((0.3, 0.0), 1e-4, settings);
+        let mut bands = 0;
+        for i in 1..7000 {
+            let escape = escape((0.3 + f64::from(i) * 1e-4, 0.0), 1e-4, settings);
+            assert!(escape.iter as f32 <= escape.smooth);
+            assert!(escape.smooth <= escape.iter as f32 + 1.0);
+            assert!((escape.smooth - last.smooth).abs() < 0.02);
+            if escape.iter != last.iter {
+                bands += 1;
             }
+            last = escape;
         }
+        assert!(bands >= 8);
     }
 
-    fn mask() -> Vec<usize> {
-        vec![0, 1, 2, 3]
+    // The orbit of `0.5 + i` goes through `-0.25 + 2i` and `-3.4375`, which
+    // escape radius 2 depending on the norm.
+    fn escape_iteration(norm: BailoutNorm) -> u64 {
+        let settings = BoundsSettings::new(100, 53, 0.0, 2.0, norm);
+        escape((0.5, 1.0), 1.0, settings).iter
+    }
+
+    #[test]
+    fn modulus_bailout() {
+        let norm = BailoutNorm::Modulus;
+        assert_eq!(norm.apply(3.0, -4.0), 25.0);
+        assert_eq!(norm.apply(1.5, 1.5), 4.5);
+        assert_eq!(escape_iteration(norm), 1);
+    }
+
+    #[test]
+    fn max_bailout() {
+        let norm = BailoutNorm::Max;
+        assert_eq!(norm.apply(3.0, -4.0), 16.0);
+        assert_eq!(norm.apply(-1.5, 1.0), 2.25);
+        assert_eq!(escape_iteration(norm), 1);
+    }
+
+    #[test]
+    fn real_bailout() {
+        let norm = BailoutNorm::Real;
+        assert_eq!(norm.apply(3.0, -4.0), 9.0);
+        assert_eq!(norm.apply(0.0, 10.0), 0.0);
+        assert_eq!(escape_iteration(norm), 2);
+    }
+
+    #[test]
+    fn imaginary_bailout() {
+        let norm = BailoutNorm::Imaginary;
+        assert_eq!(norm.apply(3.0, -4.0), 16.0);
+        assert_eq!(norm.apply(10.0, 0.0), 0.0);
+        assert_eq!(escape_iteration(norm), 1);
+    }
+
+    #[test]
+    fn sum_bailout() {
+        let norm = BailoutNorm::Sum;
+        assert_eq!(norm.apply(3.0, -4.0), 1.0);
+        assert_eq!(norm.apply(1.5, 1.5), 9.0);
+        assert_eq!(escape_iteration(norm), 2);
+    }
+
+    // Right of the cusp at 1/4 the closest point of the set is the cusp. The
+    // estimate is in pixels, and never more than twice the distance.
+    #[test]
+    fn distance_estimate_on_the_real_axis() {
+        let settings = BoundsSettings::new(1000, 53, 0.0, 1000.0, BailoutNorm::Modulus);
+        let step = 1e-3;
+        let distance = escape((1.0, 0.0), step, settings).distance * step as f32;
+        assert!((distance - 0.75).abs() < 0.15, "{}", distance);
+        for &x in &[0.3, 0.5, 2.0] {
+            let distance = escape((x, 0.0), step, settings).distance * step as f32;
+            assert!(distance > 0.0 && distance < 2.0 * (x as f32 - 0.25));
+        }
+    }
+
+    #[test]
+    fn main_components() {
+        assert_eq!(main_component(0.0, 0.0), Some(1));
+        assert_eq!(main_component(-0.5, 0.5), Some(1));
+        assert_eq!(main_component(-1.0, 0.0), Some(2));
+        assert_eq!(main_component(-1.2, 0.1), Some(2));
+        assert_eq!(main_component(0.25, 0.0), None);
+        assert_eq!(main_component(-0.75, 0.0), None);
+        assert_eq!(main_component(-1.3, 0.0), None);
+        assert_eq!(main_component(0.0, 1.0), None);
+    }
+
+    // Comparing an orbit of period `p` the way `check_bounded_real` does
+    // finds exactly `p` once the window has grown past it.
+    #[test]
+    fn periodicity_finds_the_period() {
+        for period in 1..40 {
+            let mut periodicity = Periodicity::new();
+            let mut saved = 0;
+            let found = (1..1000)
+                .map(|n| n % period)
+                .find_map(|z| {
+                    if z == saved {
+                        return Some(periodicity.period());
+                    }
+                    if periodicity.advance() {
+                        saved = z;
+                    }
+                    None
+                })
+                .unwrap();
+            assert_eq!(found, period);
+        }
     }
 }
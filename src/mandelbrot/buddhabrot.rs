@@ -0,0 +1,315 @@
+use std::cmp::Ordering;
+
+use mandelbrot::bounded::{main_component, BoundsSettings};
+use mandelbrot::compute::ComputedSet;
+use mandelbrot::layer::BOUNDED;
+
+// Samples are drawn from the square around the disc |c| <= 2 that contains
+// the whole set.
+const SAMPLE_RADIUS: f64 = 2.0;
+
+// Orbits grow past this many points as they are iterated, so a large limit
+// does not reserve memory in every batch.
+const ORBIT_CAPACITY: u64 = 1 << 16;
+
+/// Iteration limits of the red, green and blue channel. Equal limits give the
+/// plain Buddhabrot, different ones the Nebulabrot.
+#[derive(Clone, Copy)]
+pub struct BuddhabrotSettings {
+    pub samples: u64,
+    pub limits: [u64; 3],
+    pub importance: bool,
+    /// Seeds the random samples, the same seed gives the same image.
+    pub seed: u64,
+}
+
+impl BuddhabrotSettings {
+    pub fn new(samples: u64, limits: [u64; 3], importance: bool) -> BuddhabrotSettings {
+        BuddhabrotSettings {
+            samples,
+            limits,
+            importance,
+            seed: 0,
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> BuddhabrotSettings {
+        self.seed = seed;
+        self
+    }
+
+    pub fn limit(&self) -> u64 {
+        *self.limits.iter().max().unwrap()
+    }
+}
+
+/// xorshift64*, good enough to scatter samples and cheap to seed per batch.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        Random(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Sampling weights over a grid of cells covering the sampled square.
+pub struct ImportanceMap {
+    size: u32,
+    cumulative: Vec<f64>,
+}
+
+impl ImportanceMap {
+    pub fn uniform() -> ImportanceMap {
+        ImportanceMap {
+            size: 1,
+            cumulative: vec![1.0],
+        }
+    }
+
+    /// Weights each cell of a coarse render of the sampled square by the
+    /// longest escaping orbit in its neighbourhood. Cells deep inside the set
+    /// or far outside it, whose orbits never or hardly reach the view, are
+    /// then rarely sampled.
+    pub fn new(coarse: &ComputedSet) -> ImportanceMap {
+        let (size, _) = coarse.get_size();
+        let iterations = coarse
+            .get_layers()
+            .iterations
+            .as_ref()
+            .map(|iterations| {
+                iterations
+                    .iter()
+                    .map(|&iter| match iter {
+                        BOUNDED => 0.0,
+                        iter => f64::from(iter),
+                    })
+                    .collect::<Vec<f64>>()
+            })
+            .unwrap_or_else(|| vec![1.0; (size * size) as usize]);
+        let mut cumulative = Vec::with_capacity(iterations.len());
+        let mut total = 0.0;
+        for y in 0..size as i64 {
+            for x in 0..size as i64 {
+                let mut weight: f64 = 0.0;
+                for (dx, dy) in (-1..=1).flat_map(|dx| (-1..=1).map(move |dy| (dx, dy))) {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx >= 0 && ny >= 0 && nx < i64::from(size) && ny < i64::from(size) {
+                        weight = weight.max(iterations[(ny * i64::from(size) + nx) as usize]);
+                    }
+                }
+                total += weight;
+                cumulative.push(total);
+            }
+        }
+        if total == 0.0 {
+            return ImportanceMap::uniform();
+        }
+        ImportanceMap { size, cumulative }
+    }
+
+    /// A random `c` together with the factor that undoes the bias of the
+    /// weighting in the histogram.
+    fn sample(&self, random: &mut Random) -> ((f64, f64), f32) {
+        let total = self.cumulative[self.cumulative.len() - 1];
+        let target = random.next() * total;
+        // Never reports a match, so the result is the first cell whose
+        // cumulative weight exceeds the target and empty cells are skipped.
+        let cell = match self.cumulative.binary_search_by(|value| {
+            if *value <= target {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        }) {
+            Ok(cell) | Err(cell) => cell.min(self.cumulative.len() - 1),
+        };
+        let previous = if cell > 0 {
+            self.cumulative[cell - 1]
+        } else {
+            0.0
+        };
+        let weight = self.cumulative[cell] - previous;
+        let cells = self.cumulative.len() as f64;
+        let step = 2.0 * SAMPLE_RADIUS / f64::from(self.size);
+        let (x, y) = (cell as u32 % self.size, cell as u32 / self.size);
+        let c = (
+            -SAMPLE_RADIUS + (f64::from(x) + random.next()) * step,
+            -SAMPLE_RADIUS + (f64::from(y) + random.next()) * step,
+        );
+        (c, (total / (cells * weight)) as f32)
+    }
+}
+
+/// The pixel grid orbits are accumulated on.
+#[derive(Clone, Copy)]
+pub struct View {
+    pub start: (f64, f64),
+    pub step: f64,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl View {
+    fn pixel(&self, z: (f64, f64)) -> Option<usize> {
+        let x = ((z.0 - self.start.0) / self.step).floor();
+        let y = ((z.1 - self.start.1) / self.step).floor();
+        if x >= 0.0 && y >= 0.0 && x < f64::from(self.width) && y < f64::from(self.height) {
+            Some(y as usize * self.width as usize + x as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Iterates `samples` random values of `c` under `z^2 + c` and adds the orbit
+/// of each one that escapes to the channels whose limit it escaped within.
+pub fn sample_batch(
+    view: View,
+    settings: BuddhabrotSettings,
+    bounds: BoundsSettings,
+    importance: &ImportanceMap,
+    samples: u64,
+    seed: u64,
+) -> Vec<[f32; 3]> {
+    let mut histogram = vec![[0.0; 3]; view.width as usize * view.height as usize];
+    let mut random = Random::new(seed);
+    let limit = settings.limit();
+    let bailout = bounds.bailout();
+    let mut orbit = Vec::with_capacity(limit.min(ORBIT_CAPACITY) as usize);
+    for _ in 0..samples {
+        let (c, weight) = importance.sample(&mut random);
+        if main_component(c.0, c.1).is_some() {
+            continue;
+        }
+        orbit.clear();
+        let mut z = (0.0, 0.0);
+        let mut escaped = false;
+        for _ in 0..limit {
+            z = (z.0 * z.0 - z.1 * z.1 + c.0, 2.0 * z.0 * z.1 + c.1);
+            if bounds.bailout_norm.apply(z.0, z.1) >= bailout {
+                escaped = true;
+                break;
+            }
+            orbit.push(view.pixel(z));
+        }
+        if !escaped {
+            continue;
+        }
+        let length = orbit.len() as u64;
+        for (channel, &channel_limit) in settings.limits.iter().enumerate() {
+            if length < channel_limit {
+                for pixel in orbit.iter().filter_map(|&pixel| pixel) {
+                    histogram[pixel][channel] += weight;
+                }
+            }
+        }
+    }
+    histogram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sample_batch, BuddhabrotSettings, ImportanceMap, Random, View, SAMPLE_RADIUS};
+    use mandelbrot::bounded::{BailoutNorm, BoundsSettings};
+
+    // A map of `size` by `size` cells with the given weights, row by row.
+    fn weighted(size: u32, weight: impl Fn(u32, u32) -> f64) -> ImportanceMap {
+        let mut total = 0.0;
+        let mut cumulative = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                total += weight(x, y);
+                cumulative.push(total);
+            }
+        }
+        ImportanceMap { size, cumulative }
+    }
+
+    // Mean bias factor of the samples, and whether any fell in a cell of
+    // weight zero.
+    fn draw(map: &ImportanceMap, weight: impl Fn(u32, u32) -> f64) -> (f64, bool) {
+        let mut random = Random::new(7);
+        let step = 2.0 * SAMPLE_RADIUS / f64::from(map.size);
+        let samples = 200_000;
+        let mut sum = 0.0;
+        let mut empty = false;
+        for _ in 0..samples {
+            let (c, factor) = map.sample(&mut random);
+            let x = ((c.0 + SAMPLE_RADIUS) / step).floor() as u32;
+            let y = ((c.1 + SAMPLE_RADIUS) / step).floor() as u32;
+            empty |= weight(x, y) == 0.0;
+            sum += f64::from(factor);
+        }
+        (sum / f64::from(samples), empty)
+    }
+
+    #[test]
+    fn importance_weights_average_to_one() {
+        let weight = |x: u32, y: u32| f64::from(1 + x + 4 * y);
+        let (mean, _) = draw(&weighted(4, weight), weight);
+        assert!((mean - 1.0).abs() < 0.01, "{}", mean);
+        let (mean, _) = draw(&ImportanceMap::uniform(), |_, _| 1.0);
+        assert!((mean - 1.0).abs() < 1e-6, "{}", mean);
+    }
+
+    // Empty cells stand for orbits that add nothing to the image, the factors
+    // of the other cells average to the fraction of the square they cover.
+    #[test]
+    fn importance_skips_empty_cells() {
+        let weight = |x: u32, y: u32| {
+            if (x + y) % 3 == 0 {
+                0.0
+            } else {
+                f64::from(x + 1)
+            }
+        };
+        let (mean, empty) = draw(&weighted(8, weight), weight);
+        assert!(!empty);
+        let covered = (0..64).filter(|n| (n % 8 + n / 8) % 3 != 0).count() as f64 / 64.0;
+        assert!((mean - covered).abs() < 0.01, "{} {}", mean, covered);
+    }
+
+    // Every `c` of the one cell drawn from, in `[1, 1.04]` on both axes,
+    // leaves the escape radius at its second value, an orbit of length 1.
+    #[test]
+    fn channels_leave_out_longer_orbits() {
+        let map = weighted(100, |x, y| if (x, y) == (75, 75) { 1.0 } else { 0.0 });
+        let view = View {
+            start: (0.96, 0.96),
+            step: 0.01,
+            width: 10,
+            height: 10,
+        };
+        let settings = BuddhabrotSettings::new(1000, [1, 2, 100], false);
+        let bounds = BoundsSettings::new(100, 53, 0.0, 2.0, BailoutNorm::Modulus);
+        let histogram = sample_batch(view, settings, bounds, &map, 1000, 3);
+        let total = |channel: usize| histogram.iter().map(|pixel| pixel[channel]).sum::<f32>();
+        assert_eq!(total(0), 0.0);
+        assert!(total(1) > 0.0);
+        assert_eq!(total(1), total(2));
+    }
+
+    #[test]
+    fn seeds_reproduce_the_image() {
+        let view = View {
+            start: (-2.0, -1.5),
+            step: 0.1,
+            width: 30,
+            height: 30,
+        };
+        let settings = BuddhabrotSettings::new(1000, [20, 100, 500], false);
+        let bounds = BoundsSettings::new(500, 53, 0.0, 2.0, BailoutNorm::Modulus);
+        let map = ImportanceMap::uniform();
+        let image = |seed| sample_batch(view, settings, bounds, &map, 1000, seed);
+        assert_eq!(image(5), image(5));
+        assert!(image(5) != image(6));
+    }
+}
@@ -1,10 +1,26 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
 use threadpool::ThreadPool;
 
 use packed_simd::f64x4;
 use rug::{Complex, Float};
 
-use mandelbrot::bounded::{Bound, BoundsChecker, BoundsSettings};
+use mandelbrot::bounded::{Bound, BoundsChecker, BoundsSettings, Pixel};
+use mandelbrot::buddhabrot::{sample_batch, BuddhabrotSettings, ImportanceMap, View};
+use mandelbrot::expression::Expression;
+use mandelbrot::floatexp::FloatExp;
+use mandelbrot::formula::{
+    Buffalo, BurningShip, Celtic, Formula, FractalFormula, Mandelbrot, Multibrot, MultibrotReal,
+    Perpendicular, Tricorn,
+};
+use mandelbrot::interpreter::Interpreter;
+use mandelbrot::layer::{Layer, Layers};
+use mandelbrot::lyapunov;
+use mandelbrot::multidouble::{DoubleDouble, DoubleDoublex4, QuadDouble};
+use mandelbrot::newton::{Basin, Newton, Polynomial};
+use mandelbrot::perturbation::Perturbation;
+use mandelbrot::tile::{spiral, Tile};
 use ui::events::ComputeEvent;
 
 #[allow(dead_code)]
@@ -14,6 +30,11 @@ pub enum ComputeEngine {
     Double,
     MPC,
     SimdF64x4,
+    DoubleDouble,
+    QuadDouble,
+    SimdDoubleDoublex4,
+    Perturbation,
+    PerturbationFloatExp,
 }
 
 impl ComputeEngine {
@@ -22,7 +43,12 @@ impl ComputeEngine {
             Self::Single => 0,
             Self::Double => 1,
             Self::SimdF64x4 => 2,
-            Self::MPC => 3,
+            Self::DoubleDouble => 3,
+            Self::SimdDoubleDoublex4 => 4,
+            Self::QuadDouble => 5,
+            Self::MPC => 6,
+            Self::Perturbation => 7,
+            Self::PerturbationFloatExp => 8,
         }
     }
 
@@ -31,10 +57,61 @@ impl ComputeEngine {
             0 => Self::Single,
             1 => Self::Double,
             2 => Self::SimdF64x4,
-            3 => Self::MPC,
+            3 => Self::DoubleDouble,
+            4 => Self::SimdDoubleDoublex4,
+            5 => Self::QuadDouble,
+            6 => Self::MPC,
+            7 => Self::Perturbation,
+            8 => Self::PerturbationFloatExp,
             _ => Self::Double,
         }
     }
+
+    /// Bits of mantissa the engine computes with. Pixel coordinates are made
+    /// at least this precise whatever the MPC precision is set to.
+    pub fn precision(self) -> u32 {
+        match self {
+            Self::DoubleDouble | Self::SimdDoubleDoublex4 => 106,
+            Self::QuadDouble => 212,
+            _ => 53,
+        }
+    }
+
+    /// The engine a view is computed with. The reference orbit and series
+    /// approximation assume `z^2 + c` in the Mandelbrot plane, anything else
+    /// falls back to MPC for the perturbation engines. User formulas are only
+    /// interpreted in f64, f64x4 and MPC, the other engines use the closest
+    /// of those.
+    pub fn resolve(self, formula: FractalFormula, julia: bool, interpreted: bool) -> Self {
+        if interpreted {
+            return match self {
+                Self::Single | Self::Double | Self::DoubleDouble => Self::Double,
+                Self::SimdF64x4 | Self::SimdDoubleDoublex4 => Self::SimdF64x4,
+                Self::QuadDouble | Self::MPC | Self::Perturbation | Self::PerturbationFloatExp => {
+                    Self::MPC
+                }
+            };
+        }
+        match (self, formula) {
+            (Self::Perturbation, FractalFormula::Mandelbrot)
+            | (Self::PerturbationFloatExp, FractalFormula::Mandelbrot)
+                if !julia =>
+            {
+                self
+            }
+            (Self::Perturbation, _) | (Self::PerturbationFloatExp, _) => Self::MPC,
+            (engine, _) => engine,
+        }
+    }
+}
+
+/// The plane being iterated over. In the Mandelbrot plane a pixel is the
+/// parameter `c` and the orbit starts at 0, in a Julia plane the pixel is the
+/// starting value `z0` and `c` is fixed.
+#[derive(Clone)]
+pub enum Plane {
+    Mandelbrot,
+    Julia(Float, Float),
 }
 
 pub struct ComputeSettings {
@@ -45,6 +122,13 @@ pub struct ComputeSettings {
     height: u32,
     engine: ComputeEngine,
     bounds: BoundsSettings,
+    plane: Plane,
+    formula: FractalFormula,
+    expression: Option<Expression>,
+    cancel: CancelToken,
+    focus: Option<(u32, u32)>,
+    guessing: bool,
+    layers: Option<Vec<Layer>>,
 }
 
 impl Clone for ComputeSettings {
@@ -58,6 +142,13 @@ impl Clone for ComputeSettings {
             self.engine,
             self.bounds,
         )
+        .with_plane(self.plane.clone())
+        .with_formula(self.formula)
+        .with_expression(self.expression.clone())
+        .with_cancel(self.cancel.clone())
+        .with_focus(self.focus)
+        .with_guessing(self.guessing)
+        .with_layers(self.layers.clone())
     }
 }
 
@@ -79,30 +170,211 @@ impl ComputeSettings {
             height,
             engine,
             bounds,
+            plane: Plane::Mandelbrot,
+            formula: FractalFormula::Mandelbrot,
+            expression: None,
+            cancel: CancelToken::new(),
+            focus: None,
+            guessing: false,
+            layers: None,
+        }
+    }
+
+    pub fn with_plane(mut self, plane: Plane) -> ComputeSettings {
+        self.plane = plane;
+        self
+    }
+
+    pub fn with_formula(mut self, formula: FractalFormula) -> ComputeSettings {
+        self.formula = formula;
+        self
+    }
+
+    /// The expression used by `FractalFormula::Custom`.
+    pub fn with_expression(mut self, expression: Option<Expression>) -> ComputeSettings {
+        self.expression = expression;
+        self
+    }
+
+    /// Lets the computation be stopped from another thread.
+    pub fn with_cancel(mut self, cancel: CancelToken) -> ComputeSettings {
+        self.cancel = cancel;
+        self
+    }
+
+    /// The pixel rendering starts around, the center of the image by default.
+    pub fn with_focus(mut self, focus: Option<(u32, u32)>) -> ComputeSettings {
+        self.focus = focus;
+        self
+    }
+
+    /// Fills rectangles whose border escapes at a single iteration, or does
+    /// not escape, without computing their inside. Only the iteration count
+    /// and period are solid there, so this is ignored unless `with_layers`
+    /// asks for nothing else.
+    pub fn with_guessing(mut self, guessing: bool) -> ComputeSettings {
+        self.guessing = guessing;
+        self
+    }
+
+    /// The layers escape time engines compute, of those they can fill in.
+    /// All of them by default.
+    pub fn with_layers(mut self, layers: Option<Vec<Layer>>) -> ComputeSettings {
+        self.layers = layers;
+        self
+    }
+
+    pub fn get_position(&self) -> (&Float, &Float) {
+        (&self.x, &self.y)
+    }
+
+    pub fn get_scale(&self) -> &Float {
+        &self.scale
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn get_bounds(&self) -> BoundsSettings {
+        self.bounds
+    }
+
+    pub fn get_plane(&self) -> &Plane {
+        &self.plane
+    }
+
+    pub fn get_expression(&self) -> Option<&Expression> {
+        self.expression.as_ref()
+    }
+
+    pub fn get_cancel(&self) -> &CancelToken {
+        &self.cancel
+    }
+
+    pub fn get_focus(&self) -> (u32, u32) {
+        self.focus.unwrap_or((self.width / 2, self.height / 2))
+    }
+}
+
+/// Shared flag that stops the computations holding it. Work that has not
+/// started when it is set is skipped, so the result of a cancelled
+/// computation is incomplete and should be dropped.
+#[derive(Clone)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken(Arc::new(AtomicBool::new(false)))
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Settings of the Lyapunov mode, the view of `ComputeSettings` is taken as
+/// the region of `(a, b)`.
+#[derive(Clone)]
+pub struct LyapunovSettings {
+    sequence: Vec<bool>,
+    pub warmup: u64,
+    pub iterations: u64,
+}
+
+impl LyapunovSettings {
+    pub fn new(sequence: Vec<bool>, warmup: u64, iterations: u64) -> LyapunovSettings {
+        LyapunovSettings {
+            sequence,
+            warmup,
+            iterations,
         }
     }
+
+    /// Parses a sequence such as `AABAB`, `true` stands for `B`.
+    pub fn parse_sequence(source: &str) -> Result<Vec<bool>, String> {
+        let sequence = source
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| !c.is_whitespace())
+            .map(|(i, c)| match c.to_ascii_uppercase() {
+                'A' => Ok(false),
+                'B' => Ok(true),
+                _ => Err(format!("unexpected {:?} at {}, use A and B", c, i)),
+            })
+            .collect::<Result<Vec<bool>, String>>()?;
+        if sequence.is_empty() {
+            return Err(String::from("the sequence is empty"));
+        }
+        Ok(sequence)
+    }
+
+    pub fn get_sequence(&self) -> &[bool] {
+        &self.sequence
+    }
 }
 
+/// Per pixel output of a compute mode, split into layers.
+#[derive(Clone)]
 pub struct ComputedSet {
     width: u32,
     height: u32,
-    data: Option<Vec<Bound>>,
+    layers: Layers,
 }
 
 impl ComputedSet {
-    pub fn new(width: u32, height: u32, data: Vec<Bound>) -> ComputedSet {
+    pub fn new(width: u32, height: u32, layers: Layers) -> ComputedSet {
         ComputedSet {
             width,
             height,
-            data: Some(data),
+            layers,
         }
     }
 
+    /// A set of Lyapunov exponents.
+    pub fn real(width: u32, height: u32, data: Vec<f32>) -> ComputedSet {
+        ComputedSet::new(
+            width,
+            height,
+            Layers {
+                exponent: Some(data),
+                ..Layers::default()
+            },
+        )
+    }
+
     pub fn empty(width: u32, height: u32) -> ComputedSet {
-        ComputedSet {
+        ComputedSet::new(width, height, Layers::default())
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn get_layers(&self) -> &Layers {
+        &self.layers
+    }
+}
+
+/// Root basins of a Newton fractal, `data` indexes into `roots`.
+pub struct NewtonSet {
+    width: u32,
+    height: u32,
+    roots: Vec<(f64, f64)>,
+    data: Vec<Basin>,
+}
+
+impl NewtonSet {
+    pub fn new(width: u32, height: u32, roots: Vec<(f64, f64)>, data: Vec<Basin>) -> NewtonSet {
+        NewtonSet {
             width,
             height,
-            data: None,
+            roots,
+            data,
         }
     }
 
@@ -110,12 +382,213 @@ impl ComputedSet {
         (self.width, self.height)
     }
 
-    pub fn iter(&self) -> Option<std::slice::Iter<Bound>> {
-        match &self.data {
-            Some(data) => Some(data.iter()),
-            None => None,
+    pub fn get_roots(&self) -> &[(f64, f64)] {
+        &self.roots
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<Basin> {
+        self.data.iter()
+    }
+}
+
+/// Orbit visits per pixel in the red, green and blue channel.
+pub struct DensitySet {
+    width: u32,
+    height: u32,
+    data: Vec<[f32; 3]>,
+}
+
+impl DensitySet {
+    pub fn new(width: u32, height: u32, data: Vec<[f32; 3]>) -> DensitySet {
+        DensitySet {
+            width,
+            height,
+            data,
+        }
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<[f32; 3]> {
+        self.data.iter()
+    }
+}
+
+// Work items the Buddhabrot samples are split in, and the resolution of the
+// pass the importance sampling is seeded from.
+const BUDDHABROT_BATCHES: u64 = 64;
+const IMPORTANCE_SIZE: u32 = 256;
+
+// Pixel spacing of the progressive passes, each pass computes the pixels on
+// its grid that are not on the grid of the previous one.
+const PASS_STRIDES: [u32; 3] = [4, 2, 1];
+
+// Size in grid cells up to which solid guessing computes the inside of a
+// rectangle instead of splitting it further.
+const GUESS_CELLS: u32 = 4;
+
+/// Pixel coordinates of the view.
+struct Grid {
+    start: [Float; 2],
+    step: [Float; 2],
+}
+
+#[derive(Clone, Copy)]
+struct Pass {
+    stride: u32,
+    coarser: Option<u32>,
+}
+
+impl Pass {
+    /// Whether `(x, y)`, on the grid of this pass, is not on the coarser one.
+    fn computes(self, x: u32, y: u32) -> bool {
+        match self.coarser {
+            Some(coarser) => x % coarser != 0 || y % coarser != 0,
+            None => true,
+        }
+    }
+
+    /// Pixels of `tile` this pass computes, row by row.
+    fn points(self, tile: Tile) -> Vec<(u32, u32)> {
+        let stride = self.stride as usize;
+        (tile.y..tile.y + tile.height)
+            .step_by(stride)
+            .flat_map(|y| {
+                (tile.x..tile.x + tile.width)
+                    .step_by(stride)
+                    .map(move |x| (x, y))
+            })
+            .filter(|&(x, y)| self.computes(x, y))
+            .collect()
+    }
+
+    /// The image, or a tile of it, with every pixel replaced by the computed
+    /// one at the top left of its cell of the grid.
+    fn fill(self, output: &Layers, width: u32, height: u32) -> Layers {
+        let stride = self.stride as usize;
+        let width = width as usize;
+        output.gather(width * height as usize, |i| {
+            let (x, y) = (i % width, i / width);
+            (y - y % stride) * width + x - x % stride
+        })
+    }
+}
+
+/// Mariani-Silver subdivision of the grid of a pass over a tile. Grid cells
+/// are addressed by their column and row in the tile.
+struct Guess<'a, C> {
+    pass: Pass,
+    tile: Tile,
+    columns: u32,
+    known: Vec<bool>,
+    out: &'a mut Layers,
+    compute: &'a C,
+}
+
+impl<'a, C: Fn(&[(u32, u32)], &mut Layers)> Guess<'a, C> {
+    /// Computes the pixels of `pass` in `tile` into `out`, which holds the
+    /// pixels of the coarser passes already. `compute` takes image
+    /// coordinates and writes into the tile.
+    fn run(pass: Pass, tile: Tile, out: &'a mut Layers, compute: &'a C) {
+        // The other layers vary inside solid rectangles.
+        if !out.is_discrete() {
+            compute(&pass.points(tile), out);
+            return;
         }
+        let stride = pass.stride;
+        let columns = (tile.width - 1) / stride + 1;
+        let rows = (tile.height - 1) / stride + 1;
+        let known = (0..columns * rows)
+            .map(|i| !pass.computes(i % columns * stride, i / columns * stride))
+            .collect();
+        let mut guess = Guess {
+            pass,
+            tile,
+            columns,
+            known,
+            out,
+            compute,
+        };
+        guess.rectangle((0, 0), (columns - 1, rows - 1));
+    }
+
+    fn index(&self, (x, y): (u32, u32)) -> usize {
+        (y * self.pass.stride * self.tile.width + x * self.pass.stride) as usize
     }
+
+    fn compute(&mut self, cells: &[(u32, u32)]) {
+        let cells = cells
+            .iter()
+            .cloned()
+            .filter(|&(x, y)| !self.known[(y * self.columns + x) as usize])
+            .collect::<Vec<(u32, u32)>>();
+        if cells.is_empty() {
+            return;
+        }
+        let stride = self.pass.stride;
+        let points = cells
+            .iter()
+            .map(|&(x, y)| (self.tile.x + x * stride, self.tile.y + y * stride))
+            .collect::<Vec<(u32, u32)>>();
+        (self.compute)(&points, self.out);
+        for &(x, y) in &cells {
+            self.known[(y * self.columns + x) as usize] = true;
+        }
+    }
+
+    /// Fills the cells from `start` to `end` inclusive, the border first.
+    fn rectangle(&mut self, start: (u32, u32), end: (u32, u32)) {
+        let cells = (start.1..=end.1)
+            .flat_map(|y| (start.0..=end.0).map(move |x| (x, y)))
+            .collect::<Vec<(u32, u32)>>();
+        let (border, inside): (Vec<_>, Vec<_>) = cells
+            .into_iter()
+            .partition(|&(x, y)| x == start.0 || x == end.0 || y == start.1 || y == end.1);
+        self.compute(&border);
+        if inside.is_empty() {
+            return;
+        }
+        // Pixels of coarser passes inside can rule out a solid rectangle too.
+        let first = self.index(start);
+        let solid = border
+            .iter()
+            .chain(
+                inside
+                    .iter()
+                    .filter(|&&(x, y)| self.known[(y * self.columns + x) as usize]),
+            )
+            .all(|&cell| same_bound(self.out, self.index(cell), first));
+        if solid {
+            for &cell in &inside {
+                let known = &mut self.known[(cell.1 * self.columns + cell.0) as usize];
+                if !*known {
+                    *known = true;
+                    let index = self.index(cell);
+                    self.out.copy(first, index);
+                }
+            }
+        } else if end.0 - start.0 <= GUESS_CELLS && end.1 - start.1 <= GUESS_CELLS {
+            self.compute(&inside);
+        } else {
+            let middle = ((start.0 + end.0) / 2, (start.1 + end.1) / 2);
+            self.rectangle(start, middle);
+            self.rectangle((middle.0, start.1), (end.0, middle.1));
+            self.rectangle((start.0, middle.1), (middle.0, end.1));
+            self.rectangle(middle, end);
+        }
+    }
+}
+
+/// Whether solid guessing may fill in between pixels `a` and `b`: both escape
+/// at the same iteration or neither escapes and they share a period.
+fn same_bound(layers: &Layers, a: usize, b: usize) -> bool {
+    let same = |layer: &Option<Vec<u32>>| match layer {
+        Some(values) => values[a] == values[b],
+        None => true,
+    };
+    same(&layers.iterations) && same(&layers.period)
 }
 
 pub struct Compute {}
@@ -123,28 +596,155 @@ pub struct Compute {}
 impl Compute {
     pub fn compute_set(
         thread_pool: Option<&mut ThreadPool>,
-        message: Option<Sender<ComputeEvent>>,
+        message: Option<Sender<(CancelToken, ComputeEvent)>>,
         settings: &ComputeSettings,
     ) -> ComputedSet {
-        match settings.engine {
+        let interpreted = match settings.formula {
+            FractalFormula::Custom => settings.expression.is_some(),
+            _ => false,
+        };
+        let julia = match settings.plane {
+            Plane::Mandelbrot => false,
+            Plane::Julia(..) => true,
+        };
+        let engine = settings
+            .engine
+            .resolve(settings.formula, julia, interpreted);
+        let mut settings = settings.clone();
+        settings.bounds.precision = settings.bounds.precision.max(engine.precision());
+        let settings = &settings;
+        match settings.formula {
+            FractalFormula::Custom if interpreted => {
+                Self::compute_set_interpreted(engine, thread_pool, message, settings)
+            }
+            FractalFormula::Mandelbrot | FractalFormula::Custom => {
+                Self::compute_set_with_formula(Mandelbrot, engine, thread_pool, message, settings)
+            }
+            FractalFormula::Multibrot(power) => Self::compute_set_with_formula(
+                Multibrot::new(power),
+                engine,
+                thread_pool,
+                message,
+                settings,
+            ),
+            FractalFormula::MultibrotReal(power) => Self::compute_set_with_formula(
+                MultibrotReal::new(power),
+                engine,
+                thread_pool,
+                message,
+                settings,
+            ),
+            FractalFormula::BurningShip => {
+                Self::compute_set_with_formula(BurningShip, engine, thread_pool, message, settings)
+            }
+            FractalFormula::Tricorn => {
+                Self::compute_set_with_formula(Tricorn, engine, thread_pool, message, settings)
+            }
+            FractalFormula::Celtic => {
+                Self::compute_set_with_formula(Celtic, engine, thread_pool, message, settings)
+            }
+            FractalFormula::Buffalo => {
+                Self::compute_set_with_formula(Buffalo, engine, thread_pool, message, settings)
+            }
+            FractalFormula::Perpendicular => Self::compute_set_with_formula(
+                Perpendicular,
+                engine,
+                thread_pool,
+                message,
+                settings,
+            ),
+        }
+    }
+
+    // `engine` has been resolved to one of the engines the interpreter is
+    // implemented for.
+    fn compute_set_interpreted(
+        engine: ComputeEngine,
+        thread_pool: Option<&mut ThreadPool>,
+        message: Option<Sender<(CancelToken, ComputeEvent)>>,
+        settings: &ComputeSettings,
+    ) -> ComputedSet {
+        match engine {
+            ComputeEngine::SimdF64x4 => Self::compute_set_with_engine::<
+                Interpreter<f64x4>,
+                Mandelbrot,
+            >(Mandelbrot, thread_pool, message, settings),
+            ComputeEngine::MPC => {
+                Self::compute_set_with_engine::<Interpreter<Complex>, Mandelbrot>(
+                    Mandelbrot,
+                    thread_pool,
+                    message,
+                    settings,
+                )
+            }
+            _ => Self::compute_set_with_engine::<Interpreter<f64>, Mandelbrot>(
+                Mandelbrot,
+                thread_pool,
+                message,
+                settings,
+            ),
+        }
+    }
+
+    fn compute_set_with_formula<F: Formula>(
+        formula: F,
+        engine: ComputeEngine,
+        thread_pool: Option<&mut ThreadPool>,
+        message: Option<Sender<(CancelToken, ComputeEvent)>>,
+        settings: &ComputeSettings,
+    ) -> ComputedSet {
+        match engine {
             ComputeEngine::Single => {
-                Self::compute_set_with_engine::<f32>(thread_pool, message, &settings)
+                Self::compute_set_with_engine::<f32, F>(formula, thread_pool, message, settings)
             }
             ComputeEngine::Double => {
-                Self::compute_set_with_engine::<f64>(thread_pool, message, &settings)
+                Self::compute_set_with_engine::<f64, F>(formula, thread_pool, message, settings)
             }
             ComputeEngine::MPC => {
-                Self::compute_set_with_engine::<Complex>(thread_pool, message, &settings)
+                Self::compute_set_with_engine::<Complex, F>(formula, thread_pool, message, settings)
             }
             ComputeEngine::SimdF64x4 => {
-                Self::compute_set_with_engine::<f64x4>(thread_pool, message, &settings)
+                Self::compute_set_with_engine::<f64x4, F>(formula, thread_pool, message, settings)
+            }
+            ComputeEngine::DoubleDouble => Self::compute_set_with_engine::<DoubleDouble, F>(
+                formula,
+                thread_pool,
+                message,
+                settings,
+            ),
+            ComputeEngine::QuadDouble => Self::compute_set_with_engine::<QuadDouble, F>(
+                formula,
+                thread_pool,
+                message,
+                settings,
+            ),
+            ComputeEngine::SimdDoubleDoublex4 => {
+                Self::compute_set_with_engine::<DoubleDoublex4, F>(
+                    formula,
+                    thread_pool,
+                    message,
+                    settings,
+                )
             }
+            ComputeEngine::Perturbation => Self::compute_set_with_engine::<Perturbation<f64>, F>(
+                formula,
+                thread_pool,
+                message,
+                settings,
+            ),
+            ComputeEngine::PerturbationFloatExp => Self::compute_set_with_engine::<
+                Perturbation<FloatExp>,
+                F,
+            >(
+                formula, thread_pool, message, settings
+            ),
         }
     }
 
-    fn compute_set_with_engine<T: BoundsChecker + 'static>(
-        thread_pool: Option<&mut ThreadPool>,
-        message: Option<Sender<ComputeEvent>>,
+    fn compute_set_with_engine<T: BoundsChecker + 'static, F: Formula>(
+        formula: F,
+        mut thread_pool: Option<&mut ThreadPool>,
+        message: Option<Sender<(CancelToken, ComputeEvent)>>,
         settings: &ComputeSettings,
     ) -> ComputedSet {
         let precision = settings.bounds.precision;
@@ -153,106 +753,738 @@ impl Compute {
         let h = Float::with_val(precision, settings.height);
         let ratio = Float::with_val(precision, &w / &h);
 
-        let x_start = Float::with_val(
-            precision,
-            &settings.x - (Float::with_val(precision, &settings.scale * &ratio) / 2.0),
-        );
-        let x_step = Float::with_val(precision, &settings.scale * &ratio) / &w;
-        let y_start = Float::with_val(
-            precision,
-            &settings.y - (Float::with_val(precision, &settings.scale / 2.0)),
-        );
-        let y_step = Float::with_val(precision, &settings.scale / &h);
+        let grid = Arc::new(Grid {
+            start: [
+                Float::with_val(
+                    precision,
+                    &settings.x - (Float::with_val(precision, &settings.scale * &ratio) / 2.0),
+                ),
+                Float::with_val(
+                    precision,
+                    &settings.y - (Float::with_val(precision, &settings.scale / 2.0)),
+                ),
+            ],
+            step: [
+                Float::with_val(precision, &settings.scale * &ratio) / &w,
+                Float::with_val(precision, &settings.scale / &h),
+            ],
+        });
+
+        Self::send(&message, settings, ComputeEvent::Start);
+
+        let context = Arc::new(T::prepare(settings));
+        let layers = T::layers()
+            .into_iter()
+            .filter(|layer| match layer {
+                Layer::Trap => settings.bounds.trap.is_some(),
+                Layer::Stripe | Layer::Triangle => settings.bounds.stripe_density.is_some(),
+                _ => true,
+            })
+            .filter(|layer| match &settings.layers {
+                Some(layers) => layers.contains(layer),
+                None => true,
+            })
+            .collect::<Vec<Layer>>();
 
-        if let Some(sender) = &message {
-            sender.send(ComputeEvent::Start).unwrap();
+        let (width, height) = (settings.width, settings.height);
+        let mut output = Layers::new(&layers, width as usize * height as usize);
+        // Tiles near the focus come first, both because that is where the
+        // user looks and because it spreads the expensive boundary regions
+        // over the pool better than rows do.
+        let tiles = spiral(width, height, settings.get_focus());
+        // Every pixel is computed in exactly one pass, progress counts them
+        // over all passes.
+        let count = width * height;
+        let mut done = 0;
+        let guessing = settings.guessing;
+        let mut coarser = None;
+        for &stride in PASS_STRIDES.iter() {
+            let pass = Pass { stride, coarser };
+            let context = context.clone();
+            let grid = grid.clone();
+            let tile_settings = settings.clone();
+            output = Self::compute_tiles(
+                thread_pool.as_mut().map(|pool| &mut **pool),
+                settings.get_cancel().clone(),
+                width,
+                tiles.clone(),
+                output,
+                move |tile, out| {
+                    let compute = |points: &[(u32, u32)], out: &mut Layers| {
+                        Self::compute_points::<T, F>(
+                            &context,
+                            formula,
+                            points,
+                            &grid,
+                            &tile_settings,
+                            tile,
+                            out,
+                        )
+                    };
+                    if guessing {
+                        Guess::run(pass, tile, out, &compute);
+                    } else {
+                        compute(&pass.points(tile), out);
+                    }
+                },
+                |tile, out| {
+                    if message.is_some() {
+                        done += pass.points(tile).len() as u32;
+                        let set = ComputedSet::new(
+                            tile.width,
+                            tile.height,
+                            pass.fill(out, tile.width, tile.height),
+                        );
+                        Self::send(&message, settings, ComputeEvent::Tile(tile, set));
+                        Self::send(&message, settings, ComputeEvent::Progress((done, count)));
+                    }
+                },
+            );
+            if stride > 1 && message.is_some() && !settings.get_cancel().is_cancelled() {
+                let set = ComputedSet::new(width, height, pass.fill(&output, width, height));
+                Self::send(&message, settings, ComputeEvent::Pass(set));
+            }
+            coarser = Some(stride);
         }
+        Self::send(
+            &message,
+            settings,
+            ComputeEvent::Glitches(T::glitches(&context)),
+        );
+        Self::send(&message, settings, ComputeEvent::End);
+        ComputedSet::new(width, height, output)
+    }
+
+    /// Newton's method for `polynomial` over the view of `settings`, in `f64`
+    /// whatever the engine. The iteration limit is taken from the bounds.
+    pub fn compute_newton(
+        thread_pool: Option<&mut ThreadPool>,
+        message: Option<Sender<(CancelToken, ComputeEvent)>>,
+        settings: &ComputeSettings,
+        polynomial: &Polynomial,
+    ) -> NewtonSet {
+        let ratio = f64::from(settings.width) / f64::from(settings.height);
+        let scale = settings.scale.to_f64();
+        let step = scale / f64::from(settings.height);
+        let x_start = settings.x.to_f64() - scale * ratio / 2.0;
+        let y_start = settings.y.to_f64() - scale / 2.0;
+        let limit = settings.bounds.limit;
+
+        Self::send(&message, settings, ComputeEvent::Start);
+
+        let newton = Arc::new(Newton::new(polynomial.clone()));
+        let data = {
+            let newton = newton.clone();
+            Self::compute_rows(
+                thread_pool,
+                settings.get_cancel().clone(),
+                settings.width,
+                (0..settings.height).collect(),
+                vec![Basin::Diverged; settings.width as usize * settings.height as usize],
+                move |y, out| {
+                    let yy = y_start + f64::from(y) * step;
+                    for (x, out) in out.iter_mut().enumerate() {
+                        *out = newton.basin((x_start + x as f64 * step, yy), limit);
+                    }
+                },
+                |n, _, _| {
+                    Self::send(
+                        &message,
+                        settings,
+                        ComputeEvent::Progress((n, settings.height)),
+                    )
+                },
+            )
+        };
+        Self::send(&message, settings, ComputeEvent::End);
+        NewtonSet::new(
+            settings.width,
+            settings.height,
+            newton.get_roots().to_vec(),
+            data,
+        )
+    }
+
+    /// Buddhabrot of the view of `settings`, the orbits of `z^2 + c` in `f64`
+    /// whatever the formula and engine.
+    pub fn compute_buddhabrot(
+        thread_pool: Option<&mut ThreadPool>,
+        message: Option<Sender<(CancelToken, ComputeEvent)>>,
+        settings: &ComputeSettings,
+        buddhabrot: BuddhabrotSettings,
+    ) -> DensitySet {
+        let mut thread_pool = thread_pool;
+        let ratio = f64::from(settings.width) / f64::from(settings.height);
+        let scale = settings.scale.to_f64();
+        let view = View {
+            start: (
+                settings.x.to_f64() - scale * ratio / 2.0,
+                settings.y.to_f64() - scale / 2.0,
+            ),
+            step: scale / f64::from(settings.height),
+            width: settings.width,
+            height: settings.height,
+        };
+        let bounds = BoundsSettings {
+            limit: buddhabrot.limit(),
+            ..settings.bounds
+        };
 
-        let mut output = vec![Bound::Bounded; settings.width as usize * settings.height as usize];
+        Self::send(&message, settings, ComputeEvent::Start);
+
+        let importance = Arc::new(if buddhabrot.importance {
+            let coarse = ComputeSettings::new(
+                Float::with_val(53, 0.0),
+                Float::with_val(53, 0.0),
+                Float::with_val(53, 4.0),
+                IMPORTANCE_SIZE,
+                IMPORTANCE_SIZE,
+                ComputeEngine::Double,
+                bounds,
+            )
+            .with_cancel(settings.cancel.clone());
+            let pool = thread_pool.as_mut().map(|pool| &mut **pool);
+            ImportanceMap::new(&Self::compute_set(pool, None, &coarse))
+        } else {
+            ImportanceMap::uniform()
+        });
+
+        // The first `samples % BUDDHABROT_BATCHES` batches take one sample
+        // more, and every batch of every seed gets its own random sequence.
+        let samples = |n: u64| {
+            buddhabrot.samples / BUDDHABROT_BATCHES
+                + u64::from(n < buddhabrot.samples % BUDDHABROT_BATCHES)
+        };
+        let seed = |n: u64| {
+            buddhabrot
+                .seed
+                .wrapping_mul(BUDDHABROT_BATCHES)
+                .wrapping_add(n)
+        };
+        let mut density = vec![[0.0; 3]; settings.width as usize * settings.height as usize];
+        let mut add = |n: u64, histogram: Vec<[f32; 3]>| {
+            for (density, histogram) in density.iter_mut().zip(histogram.iter()) {
+                for channel in 0..3 {
+                    density[channel] += histogram[channel];
+                }
+            }
+            let progress = (n as u32 + 1, BUDDHABROT_BATCHES as u32);
+            Self::send(&message, settings, ComputeEvent::Progress(progress));
+        };
         match thread_pool {
             None => {
-                for y in 0..settings.height {
-                    let out = &mut output
-                        [(y * settings.width) as usize..((y + 1) * settings.width) as usize];
-                    Self::compute_row::<T>(
-                        y,
-                        [&x_start, &y_start],
-                        [&x_step, &y_step],
-                        out,
-                        settings.clone(),
+                for n in 0..BUDDHABROT_BATCHES {
+                    if settings.cancel.is_cancelled() {
+                        break;
+                    }
+                    add(
+                        n,
+                        sample_batch(view, buddhabrot, bounds, &importance, samples(n), seed(n)),
                     );
-                    if let Some(sender) = &message {
-                        sender
-                            .send(ComputeEvent::Progress((y, settings.height)))
-                            .unwrap();
+                }
+            }
+            Some(thread_pool) => {
+                let (tx, rx) = channel();
+                for n in 0..BUDDHABROT_BATCHES {
+                    let tx = tx.clone();
+                    let importance = importance.clone();
+                    let cancel = settings.cancel.clone();
+                    let (samples, seed) = (samples(n), seed(n));
+                    thread_pool.execute(move || {
+                        let histogram = if cancel.is_cancelled() {
+                            Vec::new()
+                        } else {
+                            sample_batch(view, buddhabrot, bounds, &importance, samples, seed)
+                        };
+                        tx.send((n, histogram)).unwrap();
+                    });
+                }
+                // Batches are added in order whatever order they finish in, the
+                // float sums and so the image only depend on the seed.
+                let mut finished = vec![None; BUDDHABROT_BATCHES as usize];
+                let mut next = 0;
+                for _ in 0..BUDDHABROT_BATCHES {
+                    let (n, histogram) = rx.recv().unwrap();
+                    finished[n as usize] = Some(histogram);
+                    while let Some(histogram) = finished.get_mut(next).and_then(Option::take) {
+                        add(next as u64, histogram);
+                        next += 1;
                     }
                 }
             }
+        }
+        Self::send(&message, settings, ComputeEvent::End);
+        DensitySet::new(settings.width, settings.height, density)
+    }
+
+    /// Lyapunov exponents over the view of `settings`, with `a` along the
+    /// horizontal axis and `b` along the vertical one.
+    pub fn compute_lyapunov(
+        thread_pool: Option<&mut ThreadPool>,
+        message: Option<Sender<(CancelToken, ComputeEvent)>>,
+        settings: &ComputeSettings,
+        lyapunov: &LyapunovSettings,
+    ) -> ComputedSet {
+        let ratio = f64::from(settings.width) / f64::from(settings.height);
+        let scale = settings.scale.to_f64();
+        let step = scale / f64::from(settings.height);
+        let a_start = settings.x.to_f64() - scale * ratio / 2.0;
+        let b_start = settings.y.to_f64() - scale / 2.0;
+
+        Self::send(&message, settings, ComputeEvent::Start);
+
+        let data = {
+            let lyapunov = lyapunov.clone();
+            Self::compute_rows(
+                thread_pool,
+                settings.get_cancel().clone(),
+                settings.width,
+                (0..settings.height).collect(),
+                vec![0.0; settings.width as usize * settings.height as usize],
+                move |y, out| {
+                    let b = b_start + f64::from(y) * step;
+                    for (x, out) in out.iter_mut().enumerate() {
+                        *out = lyapunov::exponent(&lyapunov, a_start + x as f64 * step, b);
+                    }
+                },
+                |n, _, _| {
+                    Self::send(
+                        &message,
+                        settings,
+                        ComputeEvent::Progress((n, settings.height)),
+                    )
+                },
+            )
+        };
+        Self::send(&message, settings, ComputeEvent::End);
+        ComputedSet::real(settings.width, settings.height, data)
+    }
+
+    /// Sends `event` tagged with the token of the computation, so events of
+    /// an outdated one can be told apart. Nothing is sent once it has been
+    /// cancelled.
+    fn send(
+        message: &Option<Sender<(CancelToken, ComputeEvent)>>,
+        settings: &ComputeSettings,
+        event: ComputeEvent,
+    ) {
+        if let Some(sender) = message {
+            let cancel = settings.get_cancel();
+            if !cancel.is_cancelled() {
+                sender.send((cancel.clone(), event)).unwrap();
+            }
+        }
+    }
+
+    /// Updates `rows` of an image row by row with `row`, spread over the
+    /// thread pool when one is given. The other rows are left as they are.
+    /// `finished` gets the number of rows done before it, the row and its
+    /// values, as they come in and until the computation is cancelled.
+    fn compute_rows<P, R, D>(
+        thread_pool: Option<&mut ThreadPool>,
+        cancel: CancelToken,
+        width: u32,
+        rows: Vec<u32>,
+        mut output: Vec<P>,
+        row: R,
+        mut finished: D,
+    ) -> Vec<P>
+    where
+        P: Copy + Send + 'static,
+        R: Fn(u32, &mut [P]) + Send + Sync + 'static,
+        D: FnMut(u32, u32, &[P]),
+    {
+        let count = rows.len() as u32;
+        let range = |y: u32| (y * width) as usize..((y + 1) * width) as usize;
+        match thread_pool {
+            None => {
+                for (n, &y) in rows.iter().enumerate() {
+                    if cancel.is_cancelled() {
+                        break;
+                    }
+                    row(y, &mut output[range(y)]);
+                    finished(n as u32, y, &output[range(y)]);
+                }
+            }
             Some(thread_pool) => {
+                let row = Arc::new(row);
                 let (tx, rx) = channel();
-                for y in 0..settings.height {
+                for &y in &rows {
                     let tx = tx.clone();
-                    let settings = settings.clone();
-                    let x_start = x_start.clone();
-                    let y_start = y_start.clone();
-                    let x_step = x_step.clone();
-                    let y_step = y_step.clone();
+                    let row = row.clone();
+                    let cancel = cancel.clone();
+                    let mut out = output[range(y)].to_vec();
                     thread_pool.execute(move || {
-                        let mut out = vec![Bound::Bounded; settings.width as usize];
-                        Self::compute_row::<T>(
-                            y,
-                            [&x_start, &y_start],
-                            [&x_step, &y_step],
-                            &mut out,
-                            settings,
-                        );
+                        // Cancelled rows are still reported so the loop
+                        // below finishes.
+                        if !cancel.is_cancelled() {
+                            row(y, &mut out);
+                        }
                         tx.send((y, out)).unwrap();
                     });
                 }
-                for n in 0..settings.height {
-                    let (y, row) = rx.recv().unwrap();
-                    for (input, output) in row
-                        .iter()
-                        .zip(output.iter_mut().skip((y * settings.width) as usize))
-                    {
-                        *output = *input;
-                    }
-                    if let Some(sender) = &message {
-                        sender
-                            .send(ComputeEvent::Progress((n, settings.height)))
-                            .unwrap();
+                for n in 0..count {
+                    let (y, out) = rx.recv().unwrap();
+                    output[range(y)].copy_from_slice(&out);
+                    if !cancel.is_cancelled() {
+                        finished(n, y, &out);
                     }
                 }
             }
         }
-        if let Some(sender) = &message {
-            sender.send(ComputeEvent::End).unwrap();
+        output
+    }
+
+    /// Like `compute_rows` with tiles of layers, in the order they are given.
+    /// `tile` works on the pixels of the tile row by row, `finished` gets the
+    /// tile and those pixels.
+    fn compute_tiles<R, D>(
+        thread_pool: Option<&mut ThreadPool>,
+        cancel: CancelToken,
+        width: u32,
+        tiles: Vec<Tile>,
+        mut output: Layers,
+        tile: R,
+        mut finished: D,
+    ) -> Layers
+    where
+        R: Fn(Tile, &mut Layers) + Send + Sync + 'static,
+        D: FnMut(Tile, &Layers),
+    {
+        let image_index =
+            |area: Tile, i: usize| area.index(i as u32 % area.width, i as u32 / area.width, width);
+        let copy_out =
+            |output: &Layers, area: Tile| output.gather(area.area(), |i| image_index(area, i));
+        let copy_in = |output: &mut Layers, area: Tile, values: &Layers| {
+            output.scatter(values, |i| image_index(area, i))
+        };
+        match thread_pool {
+            None => {
+                for &area in &tiles {
+                    if cancel.is_cancelled() {
+                        break;
+                    }
+                    let mut out = copy_out(&output, area);
+                    tile(area, &mut out);
+                    copy_in(&mut output, area, &out);
+                    finished(area, &out);
+                }
+            }
+            Some(thread_pool) => {
+                let tile = Arc::new(tile);
+                let (tx, rx) = channel();
+                for &area in &tiles {
+                    let tx = tx.clone();
+                    let tile = tile.clone();
+                    let cancel = cancel.clone();
+                    let mut out = copy_out(&output, area);
+                    thread_pool.execute(move || {
+                        // Cancelled tiles are still reported so the loop
+                        // below finishes.
+                        if !cancel.is_cancelled() {
+                            tile(area, &mut out);
+                        }
+                        tx.send((area, out)).unwrap();
+                    });
+                }
+                for _ in 0..tiles.len() {
+                    let (area, out) = rx.recv().unwrap();
+                    copy_in(&mut output, area, &out);
+                    if !cancel.is_cancelled() {
+                        finished(area, &out);
+                    }
+                }
+            }
         }
-        ComputedSet::new(settings.width, settings.height, output)
+        output
     }
 
-    fn compute_row<T: BoundsChecker + 'static>(
-        y: u32,
-        start: [&Float; 2],
-        step: [&Float; 2],
-        out: &mut [Bound],
-        settings: ComputeSettings,
+    /// Computes the pixels at `points` into `out`, which holds the layers of
+    /// `tile`. The engine lanes are filled with consecutive points.
+    fn compute_points<T: BoundsChecker + 'static, F: Formula>(
+        context: &T::Context,
+        formula: F,
+        points: &[(u32, u32)],
+        grid: &Grid,
+        settings: &ComputeSettings,
+        tile: Tile,
+        out: &mut Layers,
     ) {
         let step_by = T::mask().len();
         let precision = settings.bounds.precision;
-        let yy = Float::with_val(
-            precision,
-            start[1] + Float::with_val(precision, step[1] * y),
-        );
-        for x in (0..settings.width).step_by(step_by) {
+        let mut result = vec![Pixel::new(Bound::Bounded(None), std::f64::INFINITY); step_by];
+        for chunk in points.chunks(step_by) {
+            if settings.cancel.is_cancelled() {
+                return;
+            }
+            // A short last chunk repeats its last point in the unused lanes.
             let mut xx: Vec<Float> = Vec::with_capacity(step_by);
+            let mut yy: Vec<Float> = Vec::with_capacity(step_by);
             for i in 0..step_by {
-                xx.push(start[0] + step[0] * Float::with_val(precision, x + i as u32))
+                let (x, y) = chunk[i.min(chunk.len() - 1)];
+                xx.push(&grid.start[0] + &grid.step[0] * Float::with_val(precision, x));
+                yy.push(&grid.start[1] + &grid.step[1] * Float::with_val(precision, y));
             }
-            let yy = vec![Float::with_val(precision, &yy); step_by];
 
-            let out = &mut out[x as usize..x as usize + step_by];
-            T::check_bounded(&xx, &yy, settings.bounds, out);
+            T::check_bounded(
+                context,
+                formula,
+                &xx,
+                &yy,
+                &grid.step[0],
+                settings.bounds,
+                &mut result,
+            );
+            for (&(x, y), pixel) in chunk.iter().zip(&result) {
+                out.set(((y - tile.y) * tile.width + x - tile.x) as usize, pixel);
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rug::Float;
+
+    use std::cell::Cell;
+    use std::sync::mpsc::channel;
+
+    use super::{Compute, ComputeEngine, ComputeSettings, Guess, Pass, Plane, PASS_STRIDES};
+    use mandelbrot::bounded::{main_component, BailoutNorm, Bound, BoundsSettings, Escape, Pixel};
+    use mandelbrot::formula::FractalFormula;
+    use mandelbrot::interior::Interior;
+    use mandelbrot::layer::{Layer, Layers, BOUNDED};
+    use mandelbrot::tile::Tile;
+    use ui::events::ComputeEvent;
+
+    // Iteration counts of a 16 by 2 view around the Misiurewicz point `i`,
+    // where neighbouring pixels escape at different iterations at any depth.
+    fn iterations(engine: ComputeEngine, precision: u32, scale: &str, limit: u64) -> Vec<u32> {
+        let settings = ComputeSettings::new(
+            Float::with_val(precision, 0.0),
+            Float::with_val(precision, 1.0),
+            Float::with_val(precision, Float::parse(scale).unwrap()),
+            16,
+            2,
+            engine,
+            BoundsSettings::new(limit, precision, 0.0, 2.0, BailoutNorm::Modulus),
+        );
+        let set = Compute::compute_set(None, None, &settings);
+        set.get_layers().iterations.clone().unwrap()
+    }
+
+    // The multi-double engines are given the default 53 bits of MPC
+    // precision, at these depths they only match MPC when the pixel
+    // coordinates are made at their own precision.
+    #[test]
+    fn double_double_matches_mpc_at_depth() {
+        let reference = iterations(ComputeEngine::MPC, 256, "1e-20", 1000);
+        assert!(reference.iter().any(|&iter| iter != reference[0]));
+        assert_eq!(
+            iterations(ComputeEngine::DoubleDouble, 53, "1e-20", 1000),
+            reference
+        );
+        assert_eq!(
+            iterations(ComputeEngine::SimdDoubleDoublex4, 53, "1e-20", 1000),
+            reference
+        );
+    }
+
+    #[test]
+    fn quad_double_matches_mpc_at_depth() {
+        let reference = iterations(ComputeEngine::MPC, 256, "1e-40", 1000);
+        assert!(reference.iter().any(|&iter| iter != reference[0]));
+        assert_eq!(
+            iterations(ComputeEngine::QuadDouble, 53, "1e-40", 1000),
+            reference
+        );
+    }
+
+    // The reference orbit of `i` stays bounded, so these pixels are
+    // perturbed from it up to their escape.
+    #[test]
+    fn perturbation_matches_mpc_at_depth() {
+        let reference = iterations(ComputeEngine::MPC, 256, "1e-30", 1000);
+        assert!(reference.iter().any(|&iter| iter != reference[0]));
+        assert_eq!(
+            iterations(ComputeEngine::Perturbation, 256, "1e-30", 1000),
+            reference
+        );
+    }
+
+    // Past the `1e-308` limit of f64 only FloatExp deltas stay nonzero.
+    #[test]
+    fn perturbation_floatexp_matches_mpc_beyond_f64() {
+        let reference = iterations(ComputeEngine::MPC, 1500, "1e-400", 3000);
+        assert!(reference.iter().any(|&iter| iter != reference[0]));
+        assert_eq!(
+            iterations(ComputeEngine::PerturbationFloatExp, 1500, "1e-400", 3000),
+            reference
+        );
+    }
+
+    // The center is just outside the period 3 component, its reference orbit
+    // escapes long before the pixels inside the component do, and pixels
+    // next to the nucleus pass closer to 0 than the reference.
+    #[test]
+    fn perturbation_rebases_past_the_reference() {
+        let view = |engine| {
+            let settings = ComputeSettings::new(
+                Float::with_val(256, -1.76488),
+                Float::with_val(256, 0.02),
+                Float::with_val(256, 0.04),
+                24,
+                40,
+                engine,
+                BoundsSettings::new(1000, 256, 0.0, 2.0, BailoutNorm::Modulus),
+            );
+            let (tx, rx) = channel();
+            let set = Compute::compute_set(None, Some(tx), &settings);
+            let glitches: u64 = rx
+                .try_iter()
+                .filter_map(|(_, event)| match event {
+                    ComputeEvent::Glitches(glitches) => Some(glitches),
+                    _ => None,
+                })
+                .sum();
+            (set.get_layers().iterations.clone().unwrap(), glitches)
+        };
+        let (reference, _) = view(ComputeEngine::MPC);
+        assert!(reference[20 * 24 + 12] < 20);
+        assert!(reference.contains(&BOUNDED));
+        let (iterations, glitches) = view(ComputeEngine::Perturbation);
+        assert_eq!(iterations, reference);
+        assert!(glitches > 0);
+    }
+
+    #[test]
+    fn resolves_unsupported_engines() {
+        let resolve = |engine: ComputeEngine, formula, julia, interpreted| {
+            engine.resolve(formula, julia, interpreted).to_int()
+        };
+        let mandelbrot = FractalFormula::Mandelbrot;
+        let ship = FractalFormula::BurningShip;
+        let perturbation = ComputeEngine::Perturbation;
+        assert_eq!(resolve(perturbation, mandelbrot, false, false), 7);
+        assert_eq!(resolve(perturbation, mandelbrot, true, false), 6);
+        assert_eq!(resolve(perturbation, ship, false, false), 6);
+        assert_eq!(
+            resolve(ComputeEngine::PerturbationFloatExp, ship, false, false),
+            6
+        );
+        assert_eq!(resolve(ComputeEngine::QuadDouble, ship, true, false), 5);
+        let custom = FractalFormula::Custom;
+        assert_eq!(resolve(ComputeEngine::Single, custom, false, true), 1);
+        assert_eq!(resolve(ComputeEngine::DoubleDouble, custom, false, true), 1);
+        assert_eq!(
+            resolve(ComputeEngine::SimdDoubleDoublex4, custom, false, true),
+            2
+        );
+        assert_eq!(resolve(ComputeEngine::QuadDouble, custom, false, true), 6);
+        assert_eq!(resolve(perturbation, custom, false, true), 6);
+    }
+
+    // Renders `layers` of a tile of `z^2 + c` in f64 through every pass, with
+    // or without solid guessing, and counts the pixels that were computed.
+    fn render(
+        tile: Tile,
+        start: (f64, f64),
+        step: f64,
+        guessing: bool,
+        layers: &[Layer],
+    ) -> (Layers, usize) {
+        let settings = BoundsSettings::new(200, 53, 0.0, 2.0, BailoutNorm::Modulus);
+        let computed = Cell::new(0);
+        let compute = |points: &[(u32, u32)], out: &mut Layers| {
+            computed.set(computed.get() + points.len());
+            for &(x, y) in points {
+                let c = (start.0 + f64::from(x) * step, start.1 + f64::from(y) * step);
+                let interior = main_component(c.0, c.1).and_then(|period| {
+                    Interior::analyze(c, (0.0, 0.0), period, step, &Plane::Mandelbrot)
+                });
+                let mut bound = Bound::Bounded(interior);
+                let limit = if interior.is_some() {
+                    0
+                } else {
+                    settings.limit
+                };
+                let (mut z, mut dz) = ((0.0, 0.0), (0.0, 0.0));
+                for iter in 0..limit {
+                    dz = (
+                        2.0 * (z.0 * dz.0 - z.1 * dz.1) + 1.0,
+                        2.0 * (z.0 * dz.1 + z.1 * dz.0),
+                    );
+                    z = (z.0 * z.0 - z.1 * z.1 + c.0, 2.0 * z.0 * z.1 + c.1);
+                    let norm = z.0 * z.0 + z.1 * z.1;
+                    if norm > settings.bailout() {
+                        let derivative = (dz.0 * step, dz.1 * step);
+                        let escape = Escape::new(iter, norm, z, derivative, 2.0, settings);
+                        bound = Bound::Unbounded(escape);
+                        break;
+                    }
+                }
+                let index = ((y - tile.y) * tile.width + x - tile.x) as usize;
+                out.set(index, &Pixel::new(bound, std::f64::INFINITY));
+            }
+        };
+        let mut out = Layers::new(layers, tile.area());
+        let mut coarser = None;
+        for &stride in PASS_STRIDES.iter() {
+            let pass = Pass { stride, coarser };
+            if guessing {
+                Guess::run(pass, tile, &mut out, &compute);
+            } else {
+                compute(&pass.points(tile), &mut out);
+            }
+            coarser = Some(stride);
+        }
+        (out, computed.get())
+    }
+
+    #[test]
+    fn guessing_matches_a_full_render() {
+        // The right half of the main cardioid with the cusp at 1/4 and the
+        // iteration bands around it.
+        let tile = Tile {
+            x: 64,
+            y: 0,
+            width: 64,
+            height: 48,
+        };
+        let render =
+            |guessing, layers: &[Layer]| render(tile, (-0.7, -0.4), 0.01, guessing, layers);
+        let discrete = [Layer::Iterations, Layer::Period];
+        let (full, all) = render(false, &discrete);
+        let (guessed, computed) = render(true, &discrete);
+        assert_eq!(all, tile.area());
+        assert!(computed < all * 2 / 3, "{} of {} computed", computed, all);
+        let periods = full.period.as_ref().unwrap();
+        assert!(periods.contains(&0) && periods.contains(&1));
+        assert_eq!(guessed.iterations, full.iterations);
+        assert_eq!(guessed.period, full.period);
+
+        // Continuous layers are not solid over the same rectangles, every
+        // pixel is computed for them.
+        let continuous = [
+            Layer::Iterations,
+            Layer::Period,
+            Layer::Smooth,
+            Layer::Distance,
+        ];
+        let (full, _) = render(false, &continuous);
+        let (guessed, computed) = render(true, &continuous);
+        assert_eq!(computed, all);
+        let bits = |layer: &Option<Vec<f32>>| {
+            let values = layer.as_ref().unwrap();
+            values
+                .iter()
+                .map(|value| value.to_bits())
+                .collect::<Vec<u32>>()
+        };
+        let smooth = full.smooth.as_ref().unwrap();
+        assert!(smooth.iter().any(|&value| value.fract() != 0.0));
+        assert_eq!(bits(&guessed.smooth), bits(&full.smooth));
+        assert_eq!(bits(&guessed.distance), bits(&full.distance));
+        assert_eq!(guessed.iterations, full.iterations);
+    }
+}
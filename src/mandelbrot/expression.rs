@@ -0,0 +1,646 @@
+use std::ops::Div;
+
+use rug::{ops::PowAssign, Complex};
+
+use mandelbrot::formula::Arithmetic;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Function {
+    Conj,
+    /// Absolute value of the real and imaginary part separately.
+    Abs,
+    Re,
+    Im,
+}
+
+/// Expression tree of a user formula, evaluated once per iteration to give
+/// the next value of `z`.
+#[derive(Clone, Debug)]
+pub enum Expression {
+    Z,
+    C,
+    Constant(f64, f64),
+    Add(Box<Expression>, Box<Expression>),
+    Sub(Box<Expression>, Box<Expression>),
+    Mul(Box<Expression>, Box<Expression>),
+    Div(Box<Expression>, Box<Expression>),
+    Neg(Box<Expression>),
+    Power(Box<Expression>, f64),
+    Call(Function, Box<Expression>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    end: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut offset = 0;
+    while let Some(&next) = chars.peek() {
+        let start = offset;
+        if next.is_whitespace() {
+            chars.next();
+            offset += 1;
+        } else if next.is_ascii_digit() || next == '.' {
+            let mut text = String::new();
+            while let Some(&digit) = chars.peek() {
+                let exponent_sign = (digit == '-' || digit == '+') && text.ends_with('e');
+                if digit.is_ascii_digit() || digit == '.' || digit == 'e' || exponent_sign {
+                    text.push(digit);
+                    chars.next();
+                    offset += 1;
+                } else {
+                    break;
+                }
+            }
+            let value = text
+                .parse()
+                .map_err(|_| format!("invalid number '{}' at {}", text, start))?;
+            tokens.push((start, Token::Number(value)));
+        } else if next.is_alphabetic() {
+            let mut name = String::new();
+            while let Some(&letter) = chars.peek() {
+                if letter.is_alphanumeric() || letter == '_' {
+                    name.push(letter);
+                    chars.next();
+                    offset += 1;
+                } else {
+                    break;
+                }
+            }
+            tokens.push((start, Token::Name(name)));
+        } else if "+-*/^()=".contains(next) {
+            tokens.push((start, Token::Symbol(next)));
+            chars.next();
+            offset += 1;
+        } else {
+            return Err(format!("unexpected '{}' at {}", next, start));
+        }
+    }
+    Ok(tokens)
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.end, |(offset, _)| *offset)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    fn accept(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        if self.accept(symbol) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at {}", symbol, self.offset()))
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expression, String> {
+        let mut left = self.product()?;
+        loop {
+            if self.accept('+') {
+                left = Expression::Add(Box::new(left), Box::new(self.product()?));
+            } else if self.accept('-') {
+                left = Expression::Sub(Box::new(left), Box::new(self.product()?));
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<Expression, String> {
+        let mut left = self.unary()?;
+        loop {
+            if self.accept('*') {
+                left = Expression::Mul(Box::new(left), Box::new(self.unary()?));
+            } else if self.accept('/') {
+                left = Expression::Div(Box::new(left), Box::new(self.unary()?));
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        if self.accept('-') {
+            Ok(Expression::Neg(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    /// `^` binds tighter than a leading minus and is right associative, the
+    /// exponent can be any expression that evaluates to a real constant.
+    fn power(&mut self) -> Result<Expression, String> {
+        let base = self.primary()?;
+        if !self.accept('^') {
+            return Ok(base);
+        }
+        let offset = self.offset();
+        let exponent = self.unary()?;
+        match exponent.real_constant() {
+            Some(exponent) => Ok(Expression::Power(Box::new(base), exponent)),
+            None => Err(format!("expected a real constant exponent at {}", offset)),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression, String> {
+        let offset = self.offset();
+        match self.next() {
+            Some(Token::Number(value)) => {
+                if self.peek() == Some(&Token::Name(String::from("i"))) {
+                    self.position += 1;
+                    Ok(Expression::Constant(0.0, value))
+                } else {
+                    Ok(Expression::Constant(value, 0.0))
+                }
+            }
+            Some(Token::Symbol('(')) => {
+                let inner = self.sum()?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            Some(Token::Name(name)) => {
+                let function = match name.as_str() {
+                    "z" => return Ok(Expression::Z),
+                    "c" => return Ok(Expression::C),
+                    "i" => return Ok(Expression::Constant(0.0, 1.0)),
+                    "conj" => Function::Conj,
+                    "abs" => Function::Abs,
+                    "re" => Function::Re,
+                    "im" => Function::Im,
+                    _ => return Err(format!("unknown name '{}' at {}", name, offset)),
+                };
+                self.expect('(')?;
+                let argument = self.sum()?;
+                self.expect(')')?;
+                Ok(Expression::Call(function, Box::new(argument)))
+            }
+            Some(token) => Err(format!("unexpected {:?} at {}", token, offset)),
+            None => Err(format!("unexpected end of formula at {}", offset)),
+        }
+    }
+}
+
+impl Expression {
+    /// Parses a formula such as `z = z^3 - z*conj(c) + c`, the leading `z =`
+    /// is optional.
+    pub fn parse(source: &str) -> Result<Expression, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+            end: source.chars().count(),
+        };
+        if parser.tokens.len() > 2
+            && parser.tokens[0].1 == Token::Name(String::from("z"))
+            && parser.tokens[1].1 == Token::Symbol('=')
+        {
+            parser.position = 2;
+        }
+        let expression = parser.sum()?;
+        if parser.position < parser.tokens.len() {
+            return Err(format!("unexpected input at {}", parser.offset()));
+        }
+        Ok(expression)
+    }
+
+    /// Highest power of `z`, the growth rate of `|z|` after escape. Formulas
+    /// that do not grow faster than linearly are treated as quadratic.
+    pub fn degree(&self) -> f64 {
+        let degree = self.z_power();
+        if degree > 1.0 {
+            degree
+        } else {
+            2.0
+        }
+    }
+
+    /// The value of an expression that depends on neither `z` nor `c`, if
+    /// it is a finite real number.
+    fn real_constant(&self) -> Option<f64> {
+        if !self.is_constant() {
+            return None;
+        }
+        let zero: (f64, f64) = (0.0, 0.0);
+        let (re, im) = self.evaluate(&zero, &zero);
+        if im == 0.0 && re.is_finite() {
+            Some(re)
+        } else {
+            None
+        }
+    }
+
+    fn is_constant(&self) -> bool {
+        match self {
+            Expression::Z | Expression::C => false,
+            Expression::Constant(..) => true,
+            Expression::Add(a, b)
+            | Expression::Sub(a, b)
+            | Expression::Mul(a, b)
+            | Expression::Div(a, b) => a.is_constant() && b.is_constant(),
+            Expression::Neg(a) | Expression::Power(a, _) | Expression::Call(_, a) => {
+                a.is_constant()
+            }
+        }
+    }
+
+    fn z_power(&self) -> f64 {
+        match self {
+            Expression::Z => 1.0,
+            Expression::C | Expression::Constant(..) => 0.0,
+            Expression::Add(a, b) | Expression::Sub(a, b) => a.z_power().max(b.z_power()),
+            Expression::Mul(a, b) => a.z_power() + b.z_power(),
+            Expression::Div(a, b) => a.z_power() - b.z_power(),
+            Expression::Neg(a) | Expression::Call(_, a) => a.z_power(),
+            Expression::Power(a, exponent) => a.z_power() * exponent,
+        }
+    }
+
+    pub fn evaluate<N: Number>(&self, z: &N, c: &N) -> N {
+        match self {
+            Expression::Z => z.clone(),
+            Expression::C => c.clone(),
+            Expression::Constant(re, im) => N::constant(z, *re, *im),
+            Expression::Add(a, b) => a.evaluate(z, c).add(&b.evaluate(z, c)),
+            Expression::Sub(a, b) => a.evaluate(z, c).sub(&b.evaluate(z, c)),
+            Expression::Mul(a, b) => a.evaluate(z, c).mul(&b.evaluate(z, c)),
+            Expression::Div(a, b) => a.evaluate(z, c).div(&b.evaluate(z, c)),
+            Expression::Neg(a) => a.evaluate(z, c).neg(),
+            Expression::Power(a, exponent) => power(&a.evaluate(z, c), *exponent),
+            Expression::Call(function, a) => {
+                let a = a.evaluate(z, c);
+                match function {
+                    Function::Conj => a.conj(),
+                    Function::Abs => a.abs(),
+                    Function::Re => a.re(),
+                    Function::Im => a.im(),
+                }
+            }
+        }
+    }
+
+    /// Forward mode differentiation, each argument is a value together with
+    /// its derivative. For the functions that are not holomorphic this is
+    /// the directional derivative along `dz`.
+    pub fn evaluate_dual<N: Number>(&self, z: &(N, N), c: &(N, N)) -> (N, N) {
+        match self {
+            Expression::Z => z.clone(),
+            Expression::C => c.clone(),
+            Expression::Constant(re, im) => {
+                (N::constant(&z.0, *re, *im), N::constant(&z.0, 0.0, 0.0))
+            }
+            Expression::Add(a, b) => {
+                let (a, b) = (a.evaluate_dual(z, c), b.evaluate_dual(z, c));
+                (a.0.add(&b.0), a.1.add(&b.1))
+            }
+            Expression::Sub(a, b) => {
+                let (a, b) = (a.evaluate_dual(z, c), b.evaluate_dual(z, c));
+                (a.0.sub(&b.0), a.1.sub(&b.1))
+            }
+            Expression::Mul(a, b) => {
+                let (a, b) = (a.evaluate_dual(z, c), b.evaluate_dual(z, c));
+                (a.0.mul(&b.0), a.1.mul(&b.0).add(&a.0.mul(&b.1)))
+            }
+            Expression::Div(a, b) => {
+                let (a, b) = (a.evaluate_dual(z, c), b.evaluate_dual(z, c));
+                let numerator = a.1.mul(&b.0).sub(&a.0.mul(&b.1));
+                (a.0.div(&b.0), numerator.div(&b.0.mul(&b.0)))
+            }
+            Expression::Neg(a) => {
+                let a = a.evaluate_dual(z, c);
+                (a.0.neg(), a.1.neg())
+            }
+            Expression::Power(a, exponent) => {
+                let a = a.evaluate_dual(z, c);
+                let scale = N::constant(&a.0, *exponent, 0.0);
+                let derivative = scale.mul(&power(&a.0, exponent - 1.0)).mul(&a.1);
+                (power(&a.0, *exponent), derivative)
+            }
+            Expression::Call(function, a) => {
+                let a = a.evaluate_dual(z, c);
+                match function {
+                    Function::Conj => (a.0.conj(), a.1.conj()),
+                    Function::Abs => (a.0.abs(), a.0.abs_derivative(&a.1)),
+                    Function::Re => (a.0.re(), a.1.re()),
+                    Function::Im => (a.0.im(), a.1.im()),
+                }
+            }
+        }
+    }
+}
+
+// Integer powers by repeated multiplication, which is both faster and more
+// accurate than the polar form.
+const MAX_INTEGER_POWER: f64 = 64.0;
+
+fn power<N: Number>(a: &N, exponent: f64) -> N {
+    if exponent.fract() != 0.0 || exponent.abs() > MAX_INTEGER_POWER {
+        return a.powf(exponent);
+    }
+    let mut result = N::constant(a, 1.0, 0.0);
+    for _ in 0..exponent.abs() as u32 {
+        result = result.mul(a);
+    }
+    if exponent < 0.0 {
+        N::constant(a, 1.0, 0.0).div(&result)
+    } else {
+        result
+    }
+}
+
+/// Complex arithmetic the expression tree is evaluated with.
+pub trait Number: Clone {
+    /// A constant with the same precision as `like`.
+    fn constant(like: &Self, re: f64, im: f64) -> Self;
+    fn add(&self, other: &Self) -> Self;
+    fn sub(&self, other: &Self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+    fn div(&self, other: &Self) -> Self;
+    fn neg(&self) -> Self;
+    fn conj(&self) -> Self;
+    fn abs(&self) -> Self;
+    /// Derivative `d` of the argument mapped through `abs`.
+    fn abs_derivative(&self, d: &Self) -> Self;
+    fn re(&self) -> Self;
+    fn im(&self) -> Self;
+    fn powf(&self, exponent: f64) -> Self;
+}
+
+impl<A: Arithmetic + Div<Output = A>> Number for (A, A) {
+    fn constant(_like: &Self, re: f64, im: f64) -> Self {
+        (A::constant(re), A::constant(im))
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        (self.0 + other.0, self.1 + other.1)
+    }
+
+    fn sub(&self, other: &Self) -> Self {
+        (self.0 - other.0, self.1 - other.1)
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        (
+            self.0 * other.0 - self.1 * other.1,
+            self.0 * other.1 + self.1 * other.0,
+        )
+    }
+
+    fn div(&self, other: &Self) -> Self {
+        let norm = other.0 * other.0 + other.1 * other.1;
+        (
+            (self.0 * other.0 + self.1 * other.1) / norm,
+            (self.1 * other.0 - self.0 * other.1) / norm,
+        )
+    }
+
+    fn neg(&self) -> Self {
+        (-self.0, -self.1)
+    }
+
+    fn conj(&self) -> Self {
+        (self.0, -self.1)
+    }
+
+    fn abs(&self) -> Self {
+        (self.0.abs(), self.1.abs())
+    }
+
+    fn abs_derivative(&self, d: &Self) -> Self {
+        (self.0.signum() * d.0, self.1.signum() * d.1)
+    }
+
+    fn re(&self) -> Self {
+        (self.0, A::constant(0.0))
+    }
+
+    fn im(&self) -> Self {
+        (self.1, A::constant(0.0))
+    }
+
+    fn powf(&self, exponent: f64) -> Self {
+        A::powf(*self, exponent)
+    }
+}
+
+impl Number for Complex {
+    fn constant(like: &Self, re: f64, im: f64) -> Self {
+        Complex::with_val(like.prec(), (re, im))
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        Complex::with_val(self.prec(), self + other)
+    }
+
+    fn sub(&self, other: &Self) -> Self {
+        Complex::with_val(self.prec(), self - other)
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        Complex::with_val(self.prec(), self * other)
+    }
+
+    fn div(&self, other: &Self) -> Self {
+        Complex::with_val(self.prec(), self / other)
+    }
+
+    fn neg(&self) -> Self {
+        Complex::with_val(self.prec(), -self)
+    }
+
+    fn conj(&self) -> Self {
+        let mut value = self.clone();
+        value.conj_mut();
+        value
+    }
+
+    fn abs(&self) -> Self {
+        let mut value = self.clone();
+        value.mut_real().abs_mut();
+        value.mut_imag().abs_mut();
+        value
+    }
+
+    fn abs_derivative(&self, d: &Self) -> Self {
+        let mut value = d.clone();
+        if self.real().is_sign_negative() {
+            *value.mut_real() *= -1;
+        }
+        if self.imag().is_sign_negative() {
+            *value.mut_imag() *= -1;
+        }
+        value
+    }
+
+    fn re(&self) -> Self {
+        Complex::with_val(self.prec(), (self.real(), 0))
+    }
+
+    fn im(&self) -> Self {
+        Complex::with_val(self.prec(), (self.imag(), 0))
+    }
+
+    fn powf(&self, exponent: f64) -> Self {
+        let mut value = self.clone();
+        value.pow_assign(exponent);
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize, Expression, Token};
+
+    fn evaluate(source: &str, z: (f64, f64), c: (f64, f64)) -> (f64, f64) {
+        Expression::parse(source).unwrap().evaluate(&z, &c)
+    }
+
+    fn assert_close(a: (f64, f64), b: (f64, f64)) {
+        assert!(
+            (a.0 - b.0).abs() < 1e-12 && (a.1 - b.1).abs() < 1e-12,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    fn error(source: &str) -> String {
+        Expression::parse(source).unwrap_err()
+    }
+
+    #[test]
+    fn tokenizes_with_offsets() {
+        let tokens = tokenize("z^2.5 + 1e-3*conj(c)").unwrap();
+        let expected = vec![
+            (0, Token::Name(String::from("z"))),
+            (1, Token::Symbol('^')),
+            (2, Token::Number(2.5)),
+            (6, Token::Symbol('+')),
+            (8, Token::Number(1e-3)),
+            (12, Token::Symbol('*')),
+            (13, Token::Name(String::from("conj"))),
+            (17, Token::Symbol('(')),
+            (18, Token::Name(String::from("c"))),
+            (19, Token::Symbol(')')),
+        ];
+        assert_eq!(tokens, expected);
+        assert_eq!(tokenize("z % 2").unwrap_err(), "unexpected '%' at 2");
+        assert_eq!(
+            tokenize("1.2.3").unwrap_err(),
+            "invalid number '1.2.3' at 0"
+        );
+    }
+
+    #[test]
+    fn parses_with_precedence() {
+        let z = (0.5, -1.5);
+        let c = (0.25, 2.0);
+        assert_close(evaluate("z = z^2 + c", z, c), (-1.75, 0.5));
+        assert_close(evaluate("z^2 + c", z, c), (-1.75, 0.5));
+        assert_close(evaluate("1 + 2 * 3 - 4 / 2", z, c), (5.0, 0.0));
+        assert_close(evaluate("(1 + 2) * 3", z, c), (9.0, 0.0));
+        assert_close(evaluate("-2^2", z, c), (-4.0, 0.0));
+        assert_close(evaluate("2^3^2", z, c), (512.0, 0.0));
+        assert_close(evaluate("2^-1", z, c), (0.5, 0.0));
+        assert_close(evaluate("4^(1/2) + 2i", z, c), (2.0, 2.0));
+        assert_close(evaluate("z^(2*1.5)", z, c), evaluate("z*z*z", z, c));
+        assert_close(evaluate("conj(z) + abs(c - 1)", z, c), (1.25, 3.5));
+        assert_close(evaluate("re(z) + im(c) * i", z, c), (0.5, 2.0));
+    }
+
+    #[test]
+    fn finds_the_degree() {
+        let degree = |source: &str| Expression::parse(source).unwrap().degree();
+        assert_eq!(degree("z^3 - z*conj(c) + c"), 3.0);
+        assert_eq!(degree("z*z^2.5 + c"), 3.5);
+        assert_eq!(degree("z^(3/2) + c"), 1.5);
+        assert_eq!(degree("z + c"), 2.0);
+        assert_eq!(degree("c"), 2.0);
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(error("z^2 +"), "unexpected end of formula at 5");
+        assert_eq!(error("z^2 + d"), "unknown name 'd' at 6");
+        assert_eq!(error("conj z"), "expected '(' at 5");
+        assert_eq!(error("(z + c"), "expected ')' at 6");
+        assert_eq!(error("z c"), "unexpected input at 2");
+        assert_eq!(error("z^z"), "expected a real constant exponent at 2");
+        assert_eq!(error("z^i"), "expected a real constant exponent at 2");
+        assert_eq!(error("z^(1/0)"), "expected a real constant exponent at 2");
+        assert_eq!(error("z^"), "unexpected end of formula at 2");
+        assert_eq!(error("* z"), "unexpected Symbol('*') at 0");
+        assert_eq!(error(""), "unexpected end of formula at 0");
+    }
+
+    #[test]
+    fn differentiates_against_differences() {
+        let sources = [
+            "z^2 + c",
+            "z^3 - z*conj(c) + c",
+            "z^2.5 + c",
+            "(z^2 + c) / (z - 2)",
+            "z^-2 + c",
+            "abs(z)^2 + c",
+            "re(z)^2 - im(z)^2 + 2*re(z)*im(z)*i + c",
+        ];
+        let z = (0.4, -0.7);
+        let c = (-0.3, 0.2);
+        let h = 1e-7;
+        for source in sources.iter() {
+            let expression = Expression::parse(source).unwrap();
+            for &dz in &[(1.0, 0.0), (0.0, 1.0)] {
+                let no_dc = (0.0, 0.0);
+                let (value, derivative) = expression.evaluate_dual(&(z, dz), &(c, no_dc));
+                assert_close(value, expression.evaluate(&z, &c));
+                let a = expression.evaluate(&(z.0 + h * dz.0, z.1 + h * dz.1), &c);
+                let b = expression.evaluate(&(z.0 - h * dz.0, z.1 - h * dz.1), &c);
+                let difference = ((a.0 - b.0) / (2.0 * h), (a.1 - b.1) / (2.0 * h));
+                assert!(
+                    (derivative.0 - difference.0).abs() < 1e-6
+                        && (derivative.1 - difference.1).abs() < 1e-6,
+                    "{}: {:?} != {:?}",
+                    source,
+                    derivative,
+                    difference
+                );
+            }
+            // In the Mandelbrot plane z is a function of c through dz = 0, dc = 1.
+            let zero = (0.0, 0.0);
+            let derivative = expression.evaluate_dual(&(z, zero), &(c, (1.0, 0.0))).1;
+            let a = expression.evaluate(&z, &(c.0 + h, c.1));
+            let b = expression.evaluate(&z, &(c.0 - h, c.1));
+            assert!((derivative.0 - (a.0 - b.0) / (2.0 * h)).abs() < 1e-6);
+            assert!((derivative.1 - (a.1 - b.1) / (2.0 * h)).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use rug::Float;
+
+use mandelbrot::formula::{powf_f64, Arithmetic};
+use mandelbrot::perturbation::Delta;
+
+const EXPONENT_MASK: u64 = 0x7ff << 52;
+const EXPONENT_BIAS: i64 = 1023;
+
+/// A `f64` mantissa in `[1, 2)` with a separate exponent, giving double
+/// precision over a range far beyond the `1e-308` limit of `f64`.
+#[derive(Debug, Copy, Clone)]
+pub struct FloatExp {
+    mantissa: f64,
+    exponent: i64,
+}
+
+impl FloatExp {
+    pub fn new(mantissa: f64, exponent: i64) -> FloatExp {
+        if mantissa == 0.0 || !mantissa.is_finite() {
+            return FloatExp {
+                mantissa,
+                exponent: 0,
+            };
+        }
+        let mut mantissa = mantissa;
+        let mut exponent = exponent;
+        if mantissa.to_bits() & EXPONENT_MASK == 0 {
+            mantissa *= 2f64.powi(54);
+            exponent -= 54;
+        }
+        let bits = mantissa.to_bits();
+        let biased = ((bits & EXPONENT_MASK) >> 52) as i64;
+        FloatExp {
+            mantissa: f64::from_bits((bits & !EXPONENT_MASK) | ((EXPONENT_BIAS as u64) << 52)),
+            exponent: exponent + biased - EXPONENT_BIAS,
+        }
+    }
+
+    pub fn abs(self) -> FloatExp {
+        FloatExp {
+            mantissa: self.mantissa.abs(),
+            exponent: self.exponent,
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        if self.exponent > 1023 {
+            self.mantissa * std::f64::INFINITY
+        } else if self.exponent >= -1022 {
+            self.mantissa * exp2(self.exponent)
+        } else if self.exponent >= -1100 {
+            // Subnormal results are scaled in two steps so that only the last
+            // one rounds.
+            self.mantissa * exp2(self.exponent + 128) * exp2(-128)
+        } else {
+            self.mantissa * 0.0
+        }
+    }
+}
+
+/// `2^exponent` for the exponents of normal `f64` values.
+fn exp2(exponent: i64) -> f64 {
+    f64::from_bits(((exponent + EXPONENT_BIAS) as u64) << 52)
+}
+
+impl Add for FloatExp {
+    type Output = FloatExp;
+
+    fn add(self, other: FloatExp) -> FloatExp {
+        if other.mantissa == 0.0 {
+            return self;
+        }
+        if self.mantissa == 0.0 {
+            return other;
+        }
+        let (large, small) = if self.exponent >= other.exponent {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let shift = large.exponent - small.exponent;
+        if shift > 64 {
+            return large;
+        }
+        FloatExp::new(
+            large.mantissa + small.mantissa * 2f64.powi(-(shift as i32)),
+            large.exponent,
+        )
+    }
+}
+
+impl Neg for FloatExp {
+    type Output = FloatExp;
+
+    fn neg(self) -> FloatExp {
+        FloatExp {
+            mantissa: -self.mantissa,
+            exponent: self.exponent,
+        }
+    }
+}
+
+impl Sub for FloatExp {
+    type Output = FloatExp;
+
+    fn sub(self, other: FloatExp) -> FloatExp {
+        self + -other
+    }
+}
+
+impl Mul for FloatExp {
+    type Output = FloatExp;
+
+    fn mul(self, other: FloatExp) -> FloatExp {
+        FloatExp::new(
+            self.mantissa * other.mantissa,
+            self.exponent + other.exponent,
+        )
+    }
+}
+
+impl Div for FloatExp {
+    type Output = FloatExp;
+
+    fn div(self, other: FloatExp) -> FloatExp {
+        FloatExp::new(
+            self.mantissa / other.mantissa,
+            self.exponent - other.exponent,
+        )
+    }
+}
+
+impl PartialEq for FloatExp {
+    fn eq(&self, other: &FloatExp) -> bool {
+        (*self - *other).mantissa == 0.0
+    }
+}
+
+impl PartialOrd for FloatExp {
+    fn partial_cmp(&self, other: &FloatExp) -> Option<Ordering> {
+        (*self - *other).mantissa.partial_cmp(&0.0)
+    }
+}
+
+impl Delta for FloatExp {
+    fn from_float(value: &Float) -> Self {
+        let (mantissa, exponent) = value.to_f64_exp();
+        FloatExp::new(mantissa, i64::from(exponent))
+    }
+
+    fn from_f64(value: f64) -> Self {
+        FloatExp::new(value, 0)
+    }
+
+    fn to_f64(self) -> f64 {
+        FloatExp::to_f64(self)
+    }
+
+    fn to_floatexp(self) -> FloatExp {
+        self
+    }
+}
+
+impl Arithmetic for FloatExp {
+    fn constant(value: f64) -> Self {
+        FloatExp::new(value, 0)
+    }
+
+    fn abs(self) -> Self {
+        FloatExp::abs(self)
+    }
+
+    fn signum(self) -> Self {
+        FloatExp::new(self.mantissa.signum(), 0)
+    }
+
+    /// `|z|^d = m^d 2^(e d)` for the mantissas `m` scaled to the larger
+    /// exponent `e`, so that neither side leaves the range of `f64`.
+    fn powf(z: (Self, Self), d: f64) -> (Self, Self) {
+        let (re, im) = z;
+        if re.mantissa == 0.0 && im.mantissa == 0.0 {
+            return (FloatExp::new(0.0, 0), FloatExp::new(0.0, 0));
+        }
+        let exponent = match (re.mantissa == 0.0, im.mantissa == 0.0) {
+            (true, _) => im.exponent,
+            (_, true) => re.exponent,
+            _ => re.exponent.max(im.exponent),
+        };
+        let scaled = |value: FloatExp| FloatExp::new(value.mantissa, value.exponent - exponent);
+        let (re, im) = powf_f64((scaled(re).to_f64(), scaled(im).to_f64()), d);
+        let power = exponent as f64 * d;
+        let integer = power.floor();
+        let fraction = 2f64.powf(power - integer);
+        (
+            FloatExp::new(re * fraction, integer as i64),
+            FloatExp::new(im * fraction, integer as i64),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FloatExp;
+    use mandelbrot::formula::{powf_f64, Arithmetic};
+
+    #[test]
+    fn normalizes_the_mantissa() {
+        let value = FloatExp::new(3.0, 0);
+        assert_eq!((value.mantissa, value.exponent), (1.5, 1));
+        let value = FloatExp::new(-0.375, 10);
+        assert_eq!((value.mantissa, value.exponent), (-1.5, 8));
+        let value = FloatExp::new(std::f64::MIN_POSITIVE / 4.0, 0);
+        assert_eq!((value.mantissa, value.exponent), (1.0, -1024));
+        let value = FloatExp::new(0.0, 100);
+        assert_eq!((value.mantissa, value.exponent), (0.0, 0));
+    }
+
+    #[test]
+    fn adds_across_exponent_gaps() {
+        let large = FloatExp::new(1.0, 1000);
+        let sum = large + FloatExp::new(1.0, 990);
+        assert_eq!((sum.mantissa, sum.exponent), (1.0 + 1.0 / 1024.0, 1000));
+        let sum = FloatExp::new(1.0, -990) + FloatExp::new(1.0, -1000);
+        assert_eq!((sum.mantissa, sum.exponent), (1.0 + 1.0 / 1024.0, -990));
+        let sum = large + FloatExp::new(1.0, 900);
+        assert_eq!((sum.mantissa, sum.exponent), (1.0, 1000));
+        let difference = large - FloatExp::new(1.5, 999);
+        assert_eq!((difference.mantissa, difference.exponent), (1.0, 998));
+        assert_eq!((large - large).mantissa, 0.0);
+    }
+
+    #[test]
+    fn multiplies_beyond_f64_range() {
+        let product = FloatExp::new(1.5, 2000) * FloatExp::new(-1.5, -3000);
+        assert_eq!((product.mantissa, product.exponent), (-1.125, -999));
+        let product = FloatExp::new(1.5, 2000) * FloatExp::new(1.5, 2000);
+        assert_eq!((product.mantissa, product.exponent), (1.125, 4001));
+        assert_eq!(product.to_f64(), std::f64::INFINITY);
+        let quotient = FloatExp::new(1.5, -2000) / FloatExp::new(-1.5, 3000);
+        assert_eq!((quotient.mantissa, quotient.exponent), (-1.0, -5000));
+        let quotient = FloatExp::new(1.0, 0) / FloatExp::new(1.5, 0);
+        assert_eq!(quotient.exponent, -1);
+        assert!((quotient.mantissa - 4.0 / 3.0).abs() < 1e-15);
+    }
+
+    #[test]
+    fn converts_to_f64_at_the_boundaries() {
+        assert_eq!(FloatExp::new(1.0, 1023).to_f64(), 2f64.powi(1023));
+        assert_eq!(FloatExp::new(1.0, 1024).to_f64(), std::f64::INFINITY);
+        assert_eq!(FloatExp::new(-1.0, 1024).to_f64(), std::f64::NEG_INFINITY);
+        assert_eq!(FloatExp::new(1.0, 1022).to_f64(), 2f64.powi(1022));
+        assert_eq!(FloatExp::new(1.0, -1022).to_f64(), std::f64::MIN_POSITIVE);
+        assert_eq!(
+            FloatExp::new(1.5, -1023).to_f64(),
+            std::f64::MIN_POSITIVE * 0.75
+        );
+        assert_eq!(FloatExp::new(1.0, -1074).to_f64(), f64::from_bits(1));
+        assert_eq!(FloatExp::new(1.0, -1080).to_f64(), 0.0);
+        assert_eq!(FloatExp::new(1.0, -5000).to_f64(), 0.0);
+        let value = 1.2345e-300;
+        assert_eq!(FloatExp::new(value, 0).to_f64(), value);
+    }
+
+    #[test]
+    fn orders_by_value() {
+        let small = FloatExp::new(1.0, -2000);
+        assert!(small < FloatExp::new(1.0, -1999));
+        assert!(FloatExp::new(-1.0, 5) < FloatExp::new(-1.0, 4));
+        assert!(FloatExp::new(-1.0, -3000) < small);
+        assert!(FloatExp::new(1.0, 3000) > FloatExp::new(1.9, 2999));
+        assert!(FloatExp::new(0.0, 0) < small);
+        assert!(small == FloatExp::new(0.5, -1999));
+    }
+
+    fn assert_close(value: FloatExp, expected: FloatExp) {
+        assert!((value - expected).abs() <= expected.abs() * FloatExp::new(1e-12, 0));
+    }
+
+    #[test]
+    fn powers_beyond_f64_range() {
+        let z = (FloatExp::new(1.5, -3000), FloatExp::new(1.0, -3001));
+        let (re, im) = powf_f64((1.5, 0.5), 2.5);
+        let power = Arithmetic::powf(z, 2.5);
+        assert_close(power.0, FloatExp::new(re, -7500));
+        assert_close(power.1, FloatExp::new(im, -7500));
+        let root = Arithmetic::powf((FloatExp::new(1.0, -1001), FloatExp::new(0.0, 0)), 0.5);
+        assert_close(root.0, FloatExp::new(2f64.sqrt(), -501));
+        assert_eq!(root.1.mantissa, 0.0);
+        let power = Arithmetic::powf((FloatExp::new(0.0, 0), FloatExp::new(1.0, 3001)), 1.5);
+        assert_close(power.0, FloatExp::new(-1.0, 4501));
+        assert_close(power.1, FloatExp::new(1.0, 4501));
+        let (re, im) = powf_f64((-0.3, 0.7), 3.3);
+        let power = Arithmetic::powf((FloatExp::new(-0.3, 0), FloatExp::new(0.7, 0)), 3.3);
+        assert_close(power.0, FloatExp::new(re, 0));
+        assert_close(power.1, FloatExp::new(im, 0));
+    }
+}
@@ -0,0 +1,475 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+use packed_simd::f64x4;
+use rug::{ops::PowAssign, Complex};
+
+use mandelbrot::bounded::main_component;
+use mandelbrot::compute::Plane;
+use mandelbrot::interior::Interior;
+
+/// Operations the formulas need, shared by the scalar and SIMD kernels and
+/// the `FloatExp` derivative of the MPC engine.
+pub trait Arithmetic:
+    Copy + Send + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Neg<Output = Self>
+{
+    fn constant(value: f64) -> Self;
+    fn abs(self) -> Self;
+    /// `1` or `-1` following the sign of the value.
+    fn signum(self) -> Self;
+    /// `z^d` for a real power on the principal branch.
+    fn powf(z: (Self, Self), d: f64) -> (Self, Self);
+}
+
+pub fn powf_f64(z: (f64, f64), d: f64) -> (f64, f64) {
+    let modulus = z.0.hypot(z.1);
+    if modulus == 0.0 {
+        return (0.0, 0.0);
+    }
+    let modulus = modulus.powf(d);
+    let (sin, cos) = (z.1.atan2(z.0) * d).sin_cos();
+    (modulus * cos, modulus * sin)
+}
+
+pub fn powf_f64x4(z: (f64x4, f64x4), d: f64) -> (f64x4, f64x4) {
+    let mut re = [0.0; 4];
+    let mut im = [0.0; 4];
+    for i in 0..4 {
+        let lane = powf_f64((z.0.extract(i), z.1.extract(i)), d);
+        re[i] = lane.0;
+        im[i] = lane.1;
+    }
+    (
+        f64x4::from_slice_unaligned(&re),
+        f64x4::from_slice_unaligned(&im),
+    )
+}
+
+impl Arithmetic for f32 {
+    fn constant(value: f64) -> Self {
+        value as f32
+    }
+
+    fn abs(self) -> Self {
+        f32::abs(self)
+    }
+
+    fn signum(self) -> Self {
+        f32::signum(self)
+    }
+
+    fn powf(z: (Self, Self), d: f64) -> (Self, Self) {
+        let (re, im) = powf_f64((f64::from(z.0), f64::from(z.1)), d);
+        (re as f32, im as f32)
+    }
+}
+
+impl Arithmetic for f64 {
+    fn constant(value: f64) -> Self {
+        value
+    }
+
+    fn abs(self) -> Self {
+        f64::abs(self)
+    }
+
+    fn signum(self) -> Self {
+        f64::signum(self)
+    }
+
+    fn powf(z: (Self, Self), d: f64) -> (Self, Self) {
+        powf_f64(z, d)
+    }
+}
+
+impl Arithmetic for f64x4 {
+    fn constant(value: f64) -> Self {
+        f64x4::splat(value)
+    }
+
+    fn abs(self) -> Self {
+        f64x4::abs(self)
+    }
+
+    fn signum(self) -> Self {
+        self.lt(f64x4::splat(0.0))
+            .select(f64x4::splat(-1.0), f64x4::splat(1.0))
+    }
+
+    fn powf(z: (Self, Self), d: f64) -> (Self, Self) {
+        powf_f64x4(z, d)
+    }
+}
+
+fn square<A: Arithmetic>(z: (A, A)) -> (A, A) {
+    (z.0 * z.0 - z.1 * z.1, A::constant(2.0) * z.0 * z.1)
+}
+
+fn mul<A: Arithmetic>(a: (A, A), b: (A, A)) -> (A, A) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn powi<A: Arithmetic>(z: (A, A), power: u32) -> (A, A) {
+    (1..power).fold(z, |value, _| mul(value, z))
+}
+
+fn conj<A: Arithmetic>(z: (A, A)) -> (A, A) {
+    (z.0, -z.1)
+}
+
+/// The map `f` of the iteration `z -> f(z) + c`.
+pub trait Formula: Copy + Send + Sync + 'static {
+    fn apply<A: Arithmetic>(self, z: (A, A)) -> (A, A);
+    /// `f'(z) dz`. For the formulas that are not holomorphic this is the
+    /// Jacobian applied to `dz` taken as a real vector.
+    fn derivative<A: Arithmetic>(self, z: (A, A), dz: (A, A)) -> (A, A);
+    fn apply_mpc(self, z: &mut Complex);
+
+    /// Growth of `|z|` per iteration after escape, the base of the smooth
+    /// iteration count.
+    fn degree(self) -> f64 {
+        2.0
+    }
+
+    /// Period of the component containing `c` when it can be found without
+    /// iterating.
+    fn main_component(self, _c: (f64, f64)) -> Option<u64> {
+        None
+    }
+
+    fn analyze(
+        self,
+        _c: (f64, f64),
+        _z: (f64, f64),
+        _period: u64,
+        _step: f64,
+        _plane: &Plane,
+    ) -> Option<Interior> {
+        None
+    }
+}
+
+/// `z^2`
+#[derive(Clone, Copy)]
+pub struct Mandelbrot;
+
+impl Formula for Mandelbrot {
+    fn apply<A: Arithmetic>(self, z: (A, A)) -> (A, A) {
+        square(z)
+    }
+
+    fn derivative<A: Arithmetic>(self, z: (A, A), dz: (A, A)) -> (A, A) {
+        let two = A::constant(2.0);
+        let product = mul(z, dz);
+        (two * product.0, two * product.1)
+    }
+
+    fn apply_mpc(self, z: &mut Complex) {
+        z.square_mut();
+    }
+
+    fn main_component(self, c: (f64, f64)) -> Option<u64> {
+        main_component(c.0, c.1)
+    }
+
+    fn analyze(
+        self,
+        c: (f64, f64),
+        z: (f64, f64),
+        period: u64,
+        step: f64,
+        plane: &Plane,
+    ) -> Option<Interior> {
+        Interior::analyze(c, z, period, step, plane)
+    }
+}
+
+/// `z^d` for an integer power `d >= 2`.
+#[derive(Clone, Copy)]
+pub struct Multibrot(u32);
+
+impl Multibrot {
+    /// Powers below 2 are raised to 2, lower ones do not escape the way the
+    /// smooth iteration count and the derivative expect.
+    pub fn new(power: u32) -> Multibrot {
+        Multibrot(power.max(2))
+    }
+}
+
+impl Formula for Multibrot {
+    fn apply<A: Arithmetic>(self, z: (A, A)) -> (A, A) {
+        powi(z, self.0)
+    }
+
+    fn derivative<A: Arithmetic>(self, z: (A, A), dz: (A, A)) -> (A, A) {
+        let d = A::constant(f64::from(self.0));
+        let product = mul(powi(z, self.0 - 1), dz);
+        (d * product.0, d * product.1)
+    }
+
+    fn apply_mpc(self, z: &mut Complex) {
+        z.pow_assign(self.0);
+    }
+
+    fn degree(self) -> f64 {
+        f64::from(self.0)
+    }
+}
+
+/// `z^d` for a real power `d > 1`, taking the principal branch.
+#[derive(Clone, Copy)]
+pub struct MultibrotReal(f64);
+
+impl MultibrotReal {
+    /// Powers of 1 and below are raised to 1.01, see `Multibrot::new`.
+    pub fn new(power: f64) -> MultibrotReal {
+        MultibrotReal(power.max(1.01))
+    }
+}
+
+impl Formula for MultibrotReal {
+    fn apply<A: Arithmetic>(self, z: (A, A)) -> (A, A) {
+        A::powf(z, self.0)
+    }
+
+    fn derivative<A: Arithmetic>(self, z: (A, A), dz: (A, A)) -> (A, A) {
+        let d = A::constant(self.0);
+        let product = mul(A::powf(z, self.0 - 1.0), dz);
+        (d * product.0, d * product.1)
+    }
+
+    fn apply_mpc(self, z: &mut Complex) {
+        z.pow_assign(self.0);
+    }
+
+    fn degree(self) -> f64 {
+        self.0
+    }
+}
+
+/// `(|re z| + i |im z|)^2`
+#[derive(Clone, Copy)]
+pub struct BurningShip;
+
+impl Formula for BurningShip {
+    fn apply<A: Arithmetic>(self, z: (A, A)) -> (A, A) {
+        square((z.0.abs(), z.1.abs()))
+    }
+
+    fn derivative<A: Arithmetic>(self, z: (A, A), dz: (A, A)) -> (A, A) {
+        let w = (z.0.abs(), z.1.abs());
+        let dw = (z.0.signum() * dz.0, z.1.signum() * dz.1);
+        Mandelbrot.derivative(w, dw)
+    }
+
+    fn apply_mpc(self, z: &mut Complex) {
+        z.mut_real().abs_mut();
+        z.mut_imag().abs_mut();
+        z.square_mut();
+    }
+}
+
+/// `conj(z)^2`, also known as the Mandelbar set.
+#[derive(Clone, Copy)]
+pub struct Tricorn;
+
+impl Formula for Tricorn {
+    fn apply<A: Arithmetic>(self, z: (A, A)) -> (A, A) {
+        square(conj(z))
+    }
+
+    fn derivative<A: Arithmetic>(self, z: (A, A), dz: (A, A)) -> (A, A) {
+        Mandelbrot.derivative(conj(z), conj(dz))
+    }
+
+    fn apply_mpc(self, z: &mut Complex) {
+        z.conj_mut();
+        z.square_mut();
+    }
+}
+
+/// `|re z^2| + i im z^2`
+#[derive(Clone, Copy)]
+pub struct Celtic;
+
+impl Formula for Celtic {
+    fn apply<A: Arithmetic>(self, z: (A, A)) -> (A, A) {
+        let z = square(z);
+        (z.0.abs(), z.1)
+    }
+
+    fn derivative<A: Arithmetic>(self, z: (A, A), dz: (A, A)) -> (A, A) {
+        let dz = Mandelbrot.derivative(z, dz);
+        (square(z).0.signum() * dz.0, dz.1)
+    }
+
+    fn apply_mpc(self, z: &mut Complex) {
+        z.square_mut();
+        z.mut_real().abs_mut();
+    }
+}
+
+/// `|re z^2| + i |im z^2|`
+#[derive(Clone, Copy)]
+pub struct Buffalo;
+
+impl Formula for Buffalo {
+    fn apply<A: Arithmetic>(self, z: (A, A)) -> (A, A) {
+        let z = square(z);
+        (z.0.abs(), z.1.abs())
+    }
+
+    fn derivative<A: Arithmetic>(self, z: (A, A), dz: (A, A)) -> (A, A) {
+        let dz = Mandelbrot.derivative(z, dz);
+        let z = square(z);
+        (z.0.signum() * dz.0, z.1.signum() * dz.1)
+    }
+
+    fn apply_mpc(self, z: &mut Complex) {
+        z.square_mut();
+        z.mut_real().abs_mut();
+        z.mut_imag().abs_mut();
+    }
+}
+
+/// `conj((|re z| + i im z)^2)`, the perpendicular Mandelbrot set.
+#[derive(Clone, Copy)]
+pub struct Perpendicular;
+
+impl Formula for Perpendicular {
+    fn apply<A: Arithmetic>(self, z: (A, A)) -> (A, A) {
+        conj(square((z.0.abs(), z.1)))
+    }
+
+    fn derivative<A: Arithmetic>(self, z: (A, A), dz: (A, A)) -> (A, A) {
+        let w = (z.0.abs(), z.1);
+        let dw = (z.0.signum() * dz.0, dz.1);
+        conj(Mandelbrot.derivative(w, dw))
+    }
+
+    fn apply_mpc(self, z: &mut Complex) {
+        z.mut_real().abs_mut();
+        z.square_mut();
+        z.conj_mut();
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum FractalFormula {
+    Mandelbrot,
+    Multibrot(u32),
+    MultibrotReal(f64),
+    BurningShip,
+    Tricorn,
+    Celtic,
+    Buffalo,
+    Perpendicular,
+    /// A parsed user formula, see `ComputeSettings::with_expression`.
+    Custom,
+}
+
+impl FractalFormula {
+    pub fn to_int(self) -> i32 {
+        match self {
+            Self::Mandelbrot => 0,
+            Self::Multibrot(_) => 1,
+            Self::MultibrotReal(_) => 2,
+            Self::BurningShip => 3,
+            Self::Tricorn => 4,
+            Self::Celtic => 5,
+            Self::Buffalo => 6,
+            Self::Perpendicular => 7,
+            Self::Custom => 8,
+        }
+    }
+
+    pub fn from_int(value: i32) -> Self {
+        match value {
+            0 => Self::Mandelbrot,
+            1 => Self::Multibrot(3),
+            2 => Self::MultibrotReal(2.5),
+            3 => Self::BurningShip,
+            4 => Self::Tricorn,
+            5 => Self::Celtic,
+            6 => Self::Buffalo,
+            7 => Self::Perpendicular,
+            8 => Self::Custom,
+            _ => Self::Mandelbrot,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const C: (f64, f64) = (0.5, -0.25);
+
+    // One step of `z -> f(z) + c`.
+    fn iterate<F: Formula>(formula: F, z: (f64, f64)) -> (f64, f64) {
+        let z = formula.apply(z);
+        (z.0 + C.0, z.1 + C.1)
+    }
+
+    fn assert_close(a: (f64, f64), b: (f64, f64)) {
+        assert!(
+            (a.0 - b.0).abs() < 1e-12 && (a.1 - b.1).abs() < 1e-12,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn iterates_each_formula() {
+        // (-1 + 2i)^2 = -3 - 4i, (1 + 2i)^2 = -3 + 4i
+        let z = (-1.0, 2.0);
+        assert_close(iterate(Mandelbrot, z), (-2.5, -4.25));
+        assert_close(iterate(Multibrot::new(3), z), (11.5, -2.25));
+        assert_close(iterate(Multibrot::new(4), z), (-6.5, 23.75));
+        assert_close(iterate(MultibrotReal::new(2.0), z), (-2.5, -4.25));
+        assert_close(iterate(MultibrotReal::new(1.5), (4.0, 0.0)), (8.5, -0.25));
+        assert_close(iterate(BurningShip, z), (-2.5, 3.75));
+        assert_close(iterate(Tricorn, z), (-2.5, 3.75));
+        assert_close(iterate(Celtic, z), (3.5, -4.25));
+        assert_close(iterate(Buffalo, z), (3.5, 3.75));
+        assert_close(iterate(Perpendicular, z), (-2.5, -4.25));
+    }
+
+    #[test]
+    fn clamps_low_powers() {
+        let z = (-1.0, 2.0);
+        for &power in &[0, 1, 2] {
+            let formula = Multibrot::new(power);
+            assert_eq!(formula.degree(), 2.0);
+            assert_close(formula.apply(z), (-3.0, -4.0));
+            assert_close(formula.derivative(z, (1.0, 0.0)), (-2.0, 4.0));
+        }
+        for &power in &[-1.0, 0.0, 1.0] {
+            assert_eq!(MultibrotReal::new(power).degree(), 1.01);
+        }
+    }
+
+    #[test]
+    fn derivatives_match_differences() {
+        fn check<F: Formula>(formula: F) {
+            let z = (-0.7, 0.3);
+            let h = 1e-7;
+            for &dz in &[(1.0, 0.0), (0.0, 1.0)] {
+                let a = formula.apply((z.0 + h * dz.0, z.1 + h * dz.1));
+                let b = formula.apply((z.0 - h * dz.0, z.1 - h * dz.1));
+                let difference = ((a.0 - b.0) / (2.0 * h), (a.1 - b.1) / (2.0 * h));
+                let derivative = formula.derivative(z, dz);
+                assert!((derivative.0 - difference.0).abs() < 1e-6);
+                assert!((derivative.1 - difference.1).abs() < 1e-6);
+            }
+        }
+        check(Mandelbrot);
+        check(Multibrot::new(5));
+        check(MultibrotReal::new(2.5));
+        check(BurningShip);
+        check(Tricorn);
+        check(Celtic);
+        check(Buffalo);
+        check(Perpendicular);
+    }
+}
@@ -0,0 +1,161 @@
+use mandelbrot::compute::Plane;
+
+const NEWTON_STEPS: usize = 16;
+const NEWTON_TOLERANCE: f64 = 1e-14;
+const CYCLE_TOLERANCE: f64 = 1e-6;
+
+fn add(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn sub(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn mul(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn div(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    let norm = b.0 * b.0 + b.1 * b.1;
+    (
+        (a.0 * b.0 + a.1 * b.1) / norm,
+        (a.1 * b.0 - a.0 * b.1) / norm,
+    )
+}
+
+fn scale(a: (f64, f64), k: f64) -> (f64, f64) {
+    (a.0 * k, a.1 * k)
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Interior {
+    pub period: u64,
+    pub multiplier: (f32, f32),
+    pub distance: Option<f32>,
+}
+
+impl Interior {
+    /// `z` is a value of the orbit of `c` close to its attracting cycle and
+    /// `period` a multiple of the cycle length, as found by periodicity
+    /// checking. The interior distance estimate is returned in pixels, it is
+    /// only defined in the Mandelbrot plane.
+    pub fn analyze(
+        c: (f64, f64),
+        z: (f64, f64),
+        period: u64,
+        step: f64,
+        plane: &Plane,
+    ) -> Option<Interior> {
+        (1..=period)
+            .filter(|divisor| period % divisor == 0)
+            .filter_map(|divisor| Self::attracting_cycle(c, z, divisor, step, plane))
+            .next()
+    }
+
+    fn attracting_cycle(
+        c: (f64, f64),
+        z: (f64, f64),
+        period: u64,
+        step: f64,
+        plane: &Plane,
+    ) -> Option<Interior> {
+        // Newton's method on f^p(z) - z = 0 to move onto the cycle itself.
+        let mut z0 = z;
+        for _ in 0..NEWTON_STEPS {
+            let mut w = z0;
+            let mut dw = (1.0, 0.0);
+            for _ in 0..period {
+                dw = scale(mul(w, dw), 2.0);
+                w = add(mul(w, w), c);
+            }
+            let delta = div(sub(w, z0), sub(dw, (1.0, 0.0)));
+            z0 = sub(z0, delta);
+            if delta.0.hypot(delta.1) < NEWTON_TOLERANCE {
+                break;
+            }
+        }
+
+        let mut w = z0;
+        let mut dz = (1.0, 0.0);
+        let mut dc = (0.0, 0.0);
+        let mut dzz = (0.0, 0.0);
+        let mut dcz = (0.0, 0.0);
+        for _ in 0..period {
+            dcz = scale(add(mul(w, dcz), mul(dc, dz)), 2.0);
+            dzz = scale(add(mul(dz, dz), mul(w, dzz)), 2.0);
+            dc = add(scale(mul(w, dc), 2.0), (1.0, 0.0));
+            dz = scale(mul(w, dz), 2.0);
+            w = add(mul(w, w), c);
+        }
+
+        let error = sub(w, z0);
+        let converged = error.0.hypot(error.1) < CYCLE_TOLERANCE;
+        let norm = dz.0 * dz.0 + dz.1 * dz.1;
+        let attracting = norm < 1.0;
+        if !converged || !attracting {
+            return None;
+        }
+        let distance = match plane {
+            Plane::Mandelbrot => {
+                let denominator = add(dcz, div(mul(dzz, dc), sub((1.0, 0.0), dz)));
+                Some(((1.0 - norm) / denominator.0.hypot(denominator.1) / step) as f32)
+            }
+            Plane::Julia(..) => None,
+        };
+        Some(Interior {
+            period,
+            multiplier: (dz.0 as f32, dz.1 as f32),
+            distance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rug::Float;
+
+    use super::Interior;
+    use mandelbrot::compute::Plane;
+
+    #[test]
+    fn superattracting_center_of_the_cardioid() {
+        let interior = Interior::analyze((0.0, 0.0), (0.0, 0.0), 1, 1e-3, &Plane::Mandelbrot);
+        let interior = interior.unwrap();
+        assert_eq!(interior.period, 1);
+        assert_eq!(interior.multiplier, (0.0, 0.0));
+        let distance = interior.distance.unwrap();
+        assert!(distance > 0.0 && distance.is_finite());
+    }
+
+    // Periodicity checking may find a multiple of the period, the cycle is
+    // found at the smallest divisor.
+    #[test]
+    fn period_two_bulb() {
+        for &c in &[(-1.0, 0.0), (-1.1, 0.1)] {
+            let interior = Interior::analyze(c, (0.0, 0.0), 4, 1e-3, &Plane::Mandelbrot).unwrap();
+            assert_eq!(interior.period, 2);
+            let multiplier = interior.multiplier;
+            assert!(multiplier.0.hypot(multiplier.1) < 1.0);
+            let distance = interior.distance.unwrap();
+            assert!(distance > 0.0 && distance.is_finite());
+        }
+        let interior = Interior::analyze((-1.1, 0.1), (0.0, 0.0), 2, 1e-3, &Plane::Mandelbrot);
+        assert!(interior.unwrap().multiplier != (0.0, 0.0));
+    }
+
+    #[test]
+    fn julia_planes_have_no_interior_distance() {
+        let plane = Plane::Julia(Float::with_val(53, -1.0), Float::new(53));
+        let interior = Interior::analyze((-1.0, 0.0), (0.0, 0.0), 2, 1e-3, &plane);
+        assert!(interior.unwrap().distance.is_none());
+    }
+
+    // The fixed point 2 of `z^2 - 2` repels with multiplier 4.
+    #[test]
+    fn repelling_cycles_are_not_interior() {
+        let plane = Plane::Mandelbrot;
+        assert!(Interior::analyze((-2.0, 0.0), (2.0, 0.0), 1, 1e-3, &plane).is_none());
+        assert!(Interior::analyze((0.5, 0.0), (0.5, 0.0), 1, 1e-3, &plane).is_none());
+    }
+}
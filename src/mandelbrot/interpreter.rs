@@ -0,0 +1,330 @@
+use std::marker::PhantomData;
+
+use packed_simd::{f64x4, m64x4, u64x4};
+use rug::{Assign, Complex, Float};
+
+use mandelbrot::average::OrbitAverages;
+use mandelbrot::bounded::{
+    Bound, BoundsChecker, BoundsSettings, Escape, Periodicity, Pixel, PERIOD_TOLERANCE,
+};
+use mandelbrot::compute::{ComputeSettings, Plane};
+use mandelbrot::expression::Expression;
+use mandelbrot::floatexp::FloatExp;
+use mandelbrot::formula::Formula;
+use mandelbrot::layer::Layer;
+use mandelbrot::perturbation::Delta;
+use mandelbrot::trap::TrapDistance;
+
+/// A value together with its derivative with respect to the pixel.
+type Dual = ((f64, f64), (f64, f64));
+
+/// A parsed user formula with the plane it is iterated in.
+pub struct Program {
+    expression: Expression,
+    plane: Plane,
+    degree: f64,
+}
+
+impl Program {
+    fn new(settings: &ComputeSettings) -> Program {
+        let expression = settings
+            .get_expression()
+            .cloned()
+            .expect("custom formula without an expression");
+        Program {
+            degree: expression.degree(),
+            expression,
+            plane: settings.get_plane().clone(),
+        }
+    }
+
+    /// Cycles are detected but not analyzed, so there are no interior layers.
+    fn layers() -> Vec<Layer> {
+        Layer::escape_time()
+            .into_iter()
+            .filter(|layer| *layer != Layer::Period && *layer != Layer::Multiplier)
+            .collect()
+    }
+
+    /// Start value of the orbit and `c`.
+    fn start(&self, pixel: (f64, f64)) -> (Dual, Dual) {
+        match &self.plane {
+            Plane::Mandelbrot => (((0.0, 0.0), (0.0, 0.0)), (pixel, (1.0, 0.0))),
+            Plane::Julia(cx, cy) => (
+                (pixel, (1.0, 0.0)),
+                ((cx.to_f64(), cy.to_f64()), (0.0, 0.0)),
+            ),
+        }
+    }
+}
+
+/// Evaluates a user formula from its expression tree. The tree takes the
+/// place of the compiled `Formula`, which is ignored.
+pub struct Interpreter<N> {
+    _number: PhantomData<N>,
+}
+
+impl BoundsChecker for Interpreter<f64> {
+    type Context = Program;
+
+    fn prepare(settings: &ComputeSettings) -> Program {
+        Program::new(settings)
+    }
+
+    fn check_bounded<F: Formula>(
+        program: &Program,
+        _formula: F,
+        x: &[Float],
+        y: &[Float],
+        step: &Float,
+        settings: BoundsSettings,
+        out: &mut [Pixel],
+    ) {
+        let step = step.to_f64();
+        let ((mut z, mut dz), c) = program.start((x[0].to_f64(), y[0].to_f64()));
+        let bailout = settings.bailout();
+        let epsilon = step * PERIOD_TOLERANCE;
+        let mut saved = z;
+        let mut periodicity = Periodicity::new();
+        let mut trap = TrapDistance::new(settings.trap);
+        let mut averages = OrbitAverages::new(settings);
+        for iter in 0..settings.limit {
+            let next = program.expression.evaluate_dual(&(z, dz), &c);
+            z = next.0;
+            dz = next.1;
+            trap.update(z);
+            averages.update(z, c.0);
+            let norm = settings.bailout_norm.apply(z.0, z.1);
+            if norm >= bailout || norm.is_nan() {
+                let derivative = (dz.0 * step, dz.1 * step);
+                out[0] = trap
+                    .pixel(Bound::Unbounded(Escape::new(
+                        iter,
+                        norm,
+                        z,
+                        derivative,
+                        program.degree,
+                        settings,
+                    )))
+                    .with_averages(&averages);
+                return;
+            }
+            if (z.0 - saved.0).abs() < epsilon && (z.1 - saved.1).abs() < epsilon {
+                out[0] = trap.pixel(Bound::Bounded(None));
+                return;
+            }
+            if periodicity.advance() {
+                saved = z;
+            }
+        }
+        out[0] = trap.pixel(Bound::Bounded(None));
+    }
+
+    fn mask() -> Vec<usize> {
+        vec![0]
+    }
+
+    fn layers() -> Vec<Layer> {
+        Program::layers()
+    }
+}
+
+impl BoundsChecker for Interpreter<f64x4> {
+    type Context = Program;
+
+    fn prepare(settings: &ComputeSettings) -> Program {
+        Program::new(settings)
+    }
+
+    fn check_bounded<F: Formula>(
+        program: &Program,
+        _formula: F,
+        x: &[Float],
+        y: &[Float],
+        step: &Float,
+        settings: BoundsSettings,
+        out: &mut [Pixel],
+    ) {
+        let step = step.to_f64();
+        let mut lanes = [[0.0; 4]; 8];
+        for (i, (x, y)) in x.iter().zip(y.iter()).enumerate() {
+            let ((z, dz), (c, dc)) = program.start((x.to_f64(), y.to_f64()));
+            for (lane, value) in lanes
+                .iter_mut()
+                .zip(&[z.0, z.1, dz.0, dz.1, c.0, c.1, dc.0, dc.1])
+            {
+                lane[i] = *value;
+            }
+        }
+        let [zr, zi, dzr, dzi, cr, ci, dcr, dci] = lanes;
+        let load = |lane: [f64; 4]| f64x4::from_slice_unaligned(&lane);
+        let mut z = (load(zr), load(zi));
+        let mut dz = (load(dzr), load(dzi));
+        let c = ((load(cr), load(ci)), (load(dcr), load(dci)));
+
+        let check = f64x4::splat(settings.bailout());
+        let epsilon = f64x4::splat(step * PERIOD_TOLERANCE);
+        let mut iter = u64x4::splat(0);
+        let mut escaped = [f64x4::splat(0.0); 5];
+        let mut active = m64x4::splat(true);
+        let mut unbounded = m64x4::splat(false);
+        let mut saved = z;
+        let mut periodicity = Periodicity::new();
+        let mut traps = f64x4::splat(std::f64::INFINITY);
+        let mut averages = [OrbitAverages::new(settings); 4];
+        for _ in 0..settings.limit {
+            let next = program.expression.evaluate_dual(&(z, dz), &c);
+            z = next.0;
+            dz = next.1;
+            if let Some(trap) = settings.trap {
+                traps = active.select(traps.min(trap.distance_x4(z.0, z.1)), traps);
+            }
+            if settings.stripe_density.is_some() {
+                for (i, averages) in averages.iter_mut().enumerate() {
+                    if active.extract(i) {
+                        averages.update(
+                            (z.0.extract(i), z.1.extract(i)),
+                            ((c.0).0.extract(i), (c.0).1.extract(i)),
+                        );
+                    }
+                }
+            }
+            let norm = settings.bailout_norm.apply_x4(z.0, z.1);
+            let mask = norm.lt(check);
+            let escaping = active & !mask;
+            unbounded |= escaping;
+            for (escaped, value) in escaped.iter_mut().zip(&[norm, z.0, z.1, dz.0, dz.1]) {
+                *escaped = escaping.select(*value, *escaped);
+            }
+            let periodic = (z.0 - saved.0).abs().lt(epsilon) & (z.1 - saved.1).abs().lt(epsilon);
+            active = active & mask & !periodic;
+            if active.none() {
+                break;
+            }
+            iter = active.select(iter + u64x4::splat(1), iter);
+            if periodicity.advance() {
+                saved = z;
+            }
+        }
+        let [norm, zr, zi, dzr, dzi] = escaped;
+        for (i, o) in out.iter_mut().enumerate() {
+            let bound = if unbounded.extract(i) {
+                Bound::Unbounded(Escape::new(
+                    iter.extract(i),
+                    norm.extract(i),
+                    (zr.extract(i), zi.extract(i)),
+                    (dzr.extract(i) * step, dzi.extract(i) * step),
+                    program.degree,
+                    settings,
+                ))
+            } else {
+                Bound::Bounded(None)
+            };
+            *o = Pixel::new(bound, traps.extract(i)).with_averages(&averages[i]);
+        }
+    }
+
+    fn mask() -> Vec<usize> {
+        vec![0, 1, 2, 3]
+    }
+
+    fn layers() -> Vec<Layer> {
+        Program::layers()
+    }
+}
+
+impl BoundsChecker for Interpreter<Complex> {
+    type Context = Program;
+
+    fn prepare(settings: &ComputeSettings) -> Program {
+        Program::new(settings)
+    }
+
+    fn check_bounded<F: Formula>(
+        program: &Program,
+        _formula: F,
+        x: &[Float],
+        y: &[Float],
+        step: &Float,
+        settings: BoundsSettings,
+        out: &mut [Pixel],
+    ) {
+        let precision = settings.precision;
+        let pixel = Complex::with_val(precision, (&x[0], &y[0]));
+        let zero = FloatExp::from_f64(0.0);
+        let one = FloatExp::from_f64(1.0);
+        let (mut z, c, mut dz, dc) = match &program.plane {
+            Plane::Mandelbrot => (Complex::new(precision), pixel, (zero, zero), (one, zero)),
+            Plane::Julia(cx, cy) => (
+                pixel,
+                Complex::with_val(precision, (cx, cy)),
+                (one, zero),
+                (zero, zero),
+            ),
+        };
+        // The derivative is evaluated on the rounded orbit next to the full
+        // precision one. It grows like 1 / step, so it is kept in FloatExp as
+        // in the MPC engine.
+        let cf = (c.real().to_f64(), c.imag().to_f64());
+        let c_exp = (
+            (
+                FloatExp::from_float(c.real()),
+                FloatExp::from_float(c.imag()),
+            ),
+            dc,
+        );
+        let epsilon = FloatExp::from_float(step) * FloatExp::from_f64(PERIOD_TOLERANCE);
+        let bailout = settings.bailout();
+        let mut saved = z.clone();
+        let mut difference = (Float::new(precision), Float::new(precision));
+        let mut periodicity = Periodicity::new();
+        let mut trap = TrapDistance::new(settings.trap);
+        let mut averages = OrbitAverages::new(settings);
+        for iter in 0..settings.limit {
+            let z_exp = (
+                FloatExp::from_float(z.real()),
+                FloatExp::from_float(z.imag()),
+            );
+            dz = program.expression.evaluate_dual(&(z_exp, dz), &c_exp).1;
+            z = program.expression.evaluate(&z, &c);
+            let zf = (z.real().to_f64(), z.imag().to_f64());
+            trap.update(zf);
+            averages.update(zf, cf);
+            let norm = settings.bailout_norm.apply(zf.0, zf.1);
+            if norm >= bailout || norm.is_nan() {
+                let step = FloatExp::from_float(step);
+                out[0] = trap
+                    .pixel(Bound::Unbounded(Escape::new(
+                        iter,
+                        norm,
+                        zf,
+                        ((dz.0 * step).to_f64(), (dz.1 * step).to_f64()),
+                        program.degree,
+                        settings,
+                    )))
+                    .with_averages(&averages);
+                return;
+            }
+            difference.0.assign(z.real() - saved.real());
+            difference.1.assign(z.imag() - saved.imag());
+            if FloatExp::from_float(&difference.0).abs() < epsilon
+                && FloatExp::from_float(&difference.1).abs() < epsilon
+            {
+                out[0] = trap.pixel(Bound::Bounded(None));
+                return;
+            }
+            if periodicity.advance() {
+                saved.assign(&z);
+            }
+        }
+        out[0] = trap.pixel(Bound::Bounded(None));
+    }
+
+    fn mask() -> Vec<usize> {
+        vec![0]
+    }
+
+    fn layers() -> Vec<Layer> {
+        Program::layers()
+    }
+}
@@ -0,0 +1,273 @@
+use mandelbrot::bounded::{Bound, Pixel};
+
+/// Iteration count of pixels that did not escape.
+pub const BOUNDED: u32 = std::u32::MAX;
+
+/// The per pixel values a `ComputedSet` can hold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layer {
+    Iterations,
+    Smooth,
+    FinalZ,
+    Derivative,
+    Distance,
+    Period,
+    Multiplier,
+    Trap,
+    Stripe,
+    Triangle,
+    Exponent,
+}
+
+impl Layer {
+    /// Everything an escape time engine can fill in from its pixels.
+    pub fn escape_time() -> Vec<Layer> {
+        vec![
+            Layer::Iterations,
+            Layer::Smooth,
+            Layer::FinalZ,
+            Layer::Derivative,
+            Layer::Distance,
+            Layer::Period,
+            Layer::Multiplier,
+            Layer::Trap,
+            Layer::Stripe,
+            Layer::Triangle,
+        ]
+    }
+}
+
+/// Named per pixel layers, each `None` unless the compute mode produced it.
+///
+/// `iterations` is `BOUNDED` for pixels that did not escape. `smooth`,
+/// `final_z` and `derivative` are only meaningful for escaping pixels, the
+/// derivative is scaled by the pixel spacing. `distance` is the exterior
+/// distance estimate of escaping pixels and the interior one of pixels on an
+/// attracting cycle, in pixels. `period` and `multiplier`, the complex
+/// multiplier of the cycle, are zero outside of found cycles.
+#[derive(Clone, Default)]
+pub struct Layers {
+    pub iterations: Option<Vec<u32>>,
+    pub smooth: Option<Vec<f32>>,
+    pub final_z: Option<Vec<[f32; 2]>>,
+    pub derivative: Option<Vec<[f32; 2]>>,
+    pub distance: Option<Vec<f32>>,
+    pub period: Option<Vec<u32>>,
+    pub multiplier: Option<Vec<[f32; 2]>>,
+    pub trap: Option<Vec<f32>>,
+    pub stripe: Option<Vec<f32>>,
+    pub triangle: Option<Vec<f32>>,
+    pub exponent: Option<Vec<f32>>,
+}
+
+impl Layers {
+    /// `len` pixels of each of `layers`, all set to a pixel that did not
+    /// escape and has no orbit trap.
+    pub fn new(layers: &[Layer], len: usize) -> Layers {
+        let mut result = Layers::default();
+        for kind in layers {
+            match kind {
+                Layer::Iterations => result.iterations = Some(vec![BOUNDED; len]),
+                Layer::Smooth => result.smooth = Some(vec![0.0; len]),
+                Layer::FinalZ => result.final_z = Some(vec![[0.0, 0.0]; len]),
+                Layer::Derivative => result.derivative = Some(vec![[0.0, 0.0]; len]),
+                Layer::Distance => result.distance = Some(vec![std::f32::NAN; len]),
+                Layer::Period => result.period = Some(vec![0; len]),
+                Layer::Multiplier => result.multiplier = Some(vec![[0.0, 0.0]; len]),
+                Layer::Trap => result.trap = Some(vec![std::f32::INFINITY; len]),
+                Layer::Stripe => result.stripe = Some(vec![0.0; len]),
+                Layer::Triangle => result.triangle = Some(vec![0.0; len]),
+                Layer::Exponent => result.exponent = Some(vec![0.0; len]),
+            }
+        }
+        result
+    }
+
+    /// Whether these hold nothing but the iteration count and period.
+    pub fn is_discrete(&self) -> bool {
+        self.smooth.is_none()
+            && self.final_z.is_none()
+            && self.derivative.is_none()
+            && self.distance.is_none()
+            && self.multiplier.is_none()
+            && self.trap.is_none()
+            && self.stripe.is_none()
+            && self.triangle.is_none()
+            && self.exponent.is_none()
+    }
+
+    /// Writes the engine output for one pixel into the layers there are.
+    pub fn set(&mut self, index: usize, pixel: &Pixel) {
+        fn set<T>(layer: &mut Option<Vec<T>>, index: usize, value: T) {
+            if let Some(values) = layer {
+                values[index] = value;
+            }
+        }
+        match pixel.bound {
+            Bound::Unbounded(escape) => {
+                let iter = escape.iter.min(u64::from(BOUNDED - 1)) as u32;
+                set(&mut self.iterations, index, iter);
+                set(&mut self.smooth, index, escape.smooth);
+                set(&mut self.final_z, index, [escape.z.0, escape.z.1]);
+                let derivative = [escape.derivative.0, escape.derivative.1];
+                set(&mut self.derivative, index, derivative);
+                set(&mut self.distance, index, escape.distance);
+                set(&mut self.period, index, 0);
+                set(&mut self.multiplier, index, [0.0, 0.0]);
+            }
+            Bound::Bounded(interior) => {
+                set(&mut self.iterations, index, BOUNDED);
+                set(&mut self.smooth, index, 0.0);
+                set(&mut self.final_z, index, [0.0, 0.0]);
+                set(&mut self.derivative, index, [0.0, 0.0]);
+                let distance = interior.and_then(|interior| interior.distance);
+                set(&mut self.distance, index, distance.unwrap_or(std::f32::NAN));
+                let period = interior.map_or(0, |interior| interior.period as u32);
+                set(&mut self.period, index, period);
+                let multiplier = interior.map_or([0.0, 0.0], |interior| {
+                    [interior.multiplier.0, interior.multiplier.1]
+                });
+                set(&mut self.multiplier, index, multiplier);
+            }
+        }
+        set(&mut self.trap, index, pixel.trap);
+        set(&mut self.stripe, index, pixel.stripe);
+        set(&mut self.triangle, index, pixel.triangle);
+    }
+
+    /// `len` pixels with pixel `i` taken from `source(i)` of these layers.
+    pub fn gather<F: Fn(usize) -> usize>(&self, len: usize, source: F) -> Layers {
+        fn gather<T: Copy, F: Fn(usize) -> usize>(
+            layer: &Option<Vec<T>>,
+            len: usize,
+            source: &F,
+        ) -> Option<Vec<T>> {
+            layer
+                .as_ref()
+                .map(|values| (0..len).map(|i| values[source(i)]).collect())
+        }
+        Layers {
+            iterations: gather(&self.iterations, len, &source),
+            smooth: gather(&self.smooth, len, &source),
+            final_z: gather(&self.final_z, len, &source),
+            derivative: gather(&self.derivative, len, &source),
+            distance: gather(&self.distance, len, &source),
+            period: gather(&self.period, len, &source),
+            multiplier: gather(&self.multiplier, len, &source),
+            trap: gather(&self.trap, len, &source),
+            stripe: gather(&self.stripe, len, &source),
+            triangle: gather(&self.triangle, len, &source),
+            exponent: gather(&self.exponent, len, &source),
+        }
+    }
+
+    /// Writes pixel `i` of `values` to `target(i)` of these layers, which
+    /// have to hold the same kinds.
+    pub fn scatter<F: Fn(usize) -> usize>(&mut self, values: &Layers, target: F) {
+        fn scatter<T: Copy, F: Fn(usize) -> usize>(
+            layer: &mut Option<Vec<T>>,
+            values: &Option<Vec<T>>,
+            target: &F,
+        ) {
+            if let (Some(layer), Some(values)) = (layer, values) {
+                for (i, &value) in values.iter().enumerate() {
+                    layer[target(i)] = value;
+                }
+            }
+        }
+        scatter(&mut self.iterations, &values.iterations, &target);
+        scatter(&mut self.smooth, &values.smooth, &target);
+        scatter(&mut self.final_z, &values.final_z, &target);
+        scatter(&mut self.derivative, &values.derivative, &target);
+        scatter(&mut self.distance, &values.distance, &target);
+        scatter(&mut self.period, &values.period, &target);
+        scatter(&mut self.multiplier, &values.multiplier, &target);
+        scatter(&mut self.trap, &values.trap, &target);
+        scatter(&mut self.stripe, &values.stripe, &target);
+        scatter(&mut self.triangle, &values.triangle, &target);
+        scatter(&mut self.exponent, &values.exponent, &target);
+    }
+
+    /// Copies every layer of pixel `from` to pixel `to`.
+    pub fn copy(&mut self, from: usize, to: usize) {
+        fn copy<T: Copy>(layer: &mut Option<Vec<T>>, from: usize, to: usize) {
+            if let Some(values) = layer {
+                values[to] = values[from];
+            }
+        }
+        copy(&mut self.iterations, from, to);
+        copy(&mut self.smooth, from, to);
+        copy(&mut self.final_z, from, to);
+        copy(&mut self.derivative, from, to);
+        copy(&mut self.distance, from, to);
+        copy(&mut self.period, from, to);
+        copy(&mut self.multiplier, from, to);
+        copy(&mut self.trap, from, to);
+        copy(&mut self.stripe, from, to);
+        copy(&mut self.triangle, from, to);
+        copy(&mut self.exponent, from, to);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Layer, Layers, BOUNDED};
+    use mandelbrot::bounded::{Bound, Escape, Pixel};
+    use mandelbrot::interior::Interior;
+
+    fn escaping(iter: u64) -> Pixel {
+        Pixel::new(
+            Bound::Unbounded(Escape {
+                iter,
+                smooth: iter as f32 + 0.5,
+                distance: 2.0,
+                z: (3.0, 4.0),
+                derivative: (1.0, -1.0),
+            }),
+            1.5,
+        )
+    }
+
+    #[test]
+    fn sets_only_the_requested_layers() {
+        let mut layers = Layers::new(&[Layer::Iterations, Layer::Period, Layer::Trap], 3);
+        layers.set(0, &escaping(7));
+        let interior = Interior {
+            period: 3,
+            multiplier: (0.3, 0.4),
+            distance: Some(0.25),
+        };
+        layers.set(1, &Pixel::new(Bound::Bounded(Some(interior)), 0.5));
+        assert_eq!(layers.iterations, Some(vec![7, BOUNDED, BOUNDED]));
+        assert_eq!(layers.period, Some(vec![0, 3, 0]));
+        assert_eq!(layers.trap, Some(vec![1.5, 0.5, std::f32::INFINITY]));
+        assert!(layers.smooth.is_none() && layers.distance.is_none());
+
+        let mut layers = Layers::new(&Layer::escape_time(), 2);
+        layers.set(0, &escaping(u64::max_value()));
+        layers.set(1, &Pixel::new(Bound::Bounded(Some(interior)), 0.5));
+        assert_eq!(layers.iterations, Some(vec![BOUNDED - 1, BOUNDED]));
+        assert_eq!(layers.final_z, Some(vec![[3.0, 4.0], [0.0, 0.0]]));
+        assert_eq!(layers.distance, Some(vec![2.0, 0.25]));
+        assert_eq!(layers.multiplier, Some(vec![[0.0, 0.0], [0.3, 0.4]]));
+    }
+
+    #[test]
+    fn moves_pixels_between_layers() {
+        let kinds = [Layer::Iterations, Layer::Smooth];
+        let mut image = Layers::new(&kinds, 6);
+        let mut tile = Layers::new(&kinds, 2);
+        tile.set(0, &escaping(1));
+        tile.set(1, &escaping(2));
+        image.scatter(&tile, |i| 2 * i + 1);
+        assert_eq!(
+            image.iterations,
+            Some(vec![BOUNDED, 1, BOUNDED, 2, BOUNDED, BOUNDED])
+        );
+        image.copy(3, 4);
+        let gathered = image.gather(3, |i| i + 2);
+        assert_eq!(gathered.iterations, Some(vec![BOUNDED, 2, 2]));
+        assert_eq!(gathered.smooth, Some(vec![0.0, 2.5, 2.5]));
+        assert!(gathered.period.is_none());
+    }
+}
@@ -0,0 +1,73 @@
+use mandelbrot::compute::LyapunovSettings;
+
+// Orbits start from the critical point of the logistic map.
+const START: f64 = 0.5;
+
+/// Lyapunov exponent of the logistic map `x -> r x (1 - x)`, where `r`
+/// alternates between `a` and `b` following the sequence. Negative values
+/// are stable, positive ones chaotic. Orbits that leave `[0, 1]` give NaN.
+pub fn exponent(settings: &LyapunovSettings, a: f64, b: f64) -> f32 {
+    let sequence = settings.get_sequence();
+    let rate = |n: u64| {
+        if sequence[(n % sequence.len() as u64) as usize] {
+            b
+        } else {
+            a
+        }
+    };
+    let mut x = START;
+    for n in 0..settings.warmup {
+        x *= rate(n) * (1.0 - x);
+    }
+    let mut sum = 0.0;
+    for n in settings.warmup..settings.warmup + settings.iterations {
+        let r = rate(n);
+        let derivative = (r * (1.0 - 2.0 * x)).abs();
+        if derivative == 0.0 {
+            // A superstable orbit.
+            return std::f32::NEG_INFINITY;
+        }
+        sum += derivative.ln();
+        x *= r * (1.0 - x);
+        if !(0.0..=1.0).contains(&x) {
+            return std::f32::NAN;
+        }
+    }
+    (sum / settings.iterations as f64) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::exponent;
+    use mandelbrot::compute::LyapunovSettings;
+
+    #[test]
+    fn parses_sequences() {
+        let parse = LyapunovSettings::parse_sequence;
+        assert_eq!(parse("AB"), Ok(vec![false, true]));
+        assert_eq!(parse(" a b\tB "), Ok(vec![false, true, true]));
+        assert!(parse("").is_err());
+        assert!(parse("  \n").is_err());
+    }
+
+    // Positions count every character of the source, whitespace included.
+    #[test]
+    fn reports_the_position_of_bad_characters() {
+        let parse = LyapunovSettings::parse_sequence;
+        let error = parse("ABC").unwrap_err();
+        assert!(error.contains("'C' at 2"), "{}", error);
+        let error = parse("A B 1").unwrap_err();
+        assert!(error.contains("'1' at 4"), "{}", error);
+    }
+
+    // With `a = b = r` the map has the fixed point `1 - 1/r`, attracting
+    // with multiplier `2 - r` for `r = 2.5`, and is chaotic at `r = 3.9`.
+    #[test]
+    fn exponent_sign() {
+        let settings = LyapunovSettings::new(vec![false, true], 200, 2000);
+        let stable = exponent(&settings, 2.5, 2.5);
+        assert!((f64::from(stable) - 0.5f64.ln()).abs() < 1e-3, "{}", stable);
+        let chaotic = exponent(&settings, 3.9, 3.9);
+        assert!(chaotic > 0.3, "{}", chaotic);
+    }
+}
@@ -1,2 +1,16 @@
+pub mod average;
 pub mod bounded;
+pub mod buddhabrot;
 pub mod compute;
+pub mod expression;
+pub mod floatexp;
+pub mod formula;
+pub mod interior;
+pub mod interpreter;
+pub mod layer;
+pub mod lyapunov;
+pub mod multidouble;
+pub mod newton;
+pub mod perturbation;
+pub mod tile;
+pub mod trap;
@@ -0,0 +1,667 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+use packed_simd::f64x4;
+use rug::Float;
+
+use mandelbrot::bounded::{Real, SimdReal};
+use mandelbrot::formula::Arithmetic;
+
+// Error-free transformations after Dekker and Knuth, the building blocks of
+// double-double and quad-double arithmetic. `two_prod` uses Veltkamp
+// splitting instead of fused multiply-add so the same code vectorizes.
+
+const SPLITTER: f64 = 134_217_729.0;
+
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+fn split(a: f64) -> (f64, f64) {
+    let t = SPLITTER * a;
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    let (ah, al) = split(a);
+    let (bh, bl) = split(b);
+    (p, ((ah * bh - p) + ah * bl + al * bh) + al * bl)
+}
+
+fn three_sum(a: f64, b: f64, c: f64) -> (f64, f64, f64) {
+    let (t1, t2) = two_sum(a, b);
+    let (a, t3) = two_sum(c, t1);
+    let (b, c) = two_sum(t2, t3);
+    (a, b, c)
+}
+
+fn three_sum2(a: f64, b: f64, c: f64) -> (f64, f64) {
+    let (t1, t2) = two_sum(a, b);
+    let (a, t3) = two_sum(c, t1);
+    (a, t2 + t3)
+}
+
+// Halvings of the argument of `exp` before its Taylor series.
+const EXP_HALVINGS: i32 = 12;
+
+/// What the elementary functions behind `powf` need beyond `Arithmetic`.
+trait MultiDouble: Arithmetic {
+    /// Newton steps that take an `f64` estimate to full precision.
+    const STEPS: u32;
+    /// Terms of the Taylor series of `exp` on a reduced argument.
+    const TERMS: u32;
+    const LN2: Self;
+    const TWO_PI: Self;
+
+    fn leading(self) -> f64;
+    /// Multiplies by `factor`, a power of two.
+    fn scale(self, factor: f64) -> Self;
+}
+
+fn complex_mul<M: MultiDouble>(a: (M, M), b: (M, M)) -> (M, M) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn recip<M: MultiDouble>(a: M) -> M {
+    let one = M::constant(1.0);
+    let mut x = M::constant(1.0 / a.leading());
+    for _ in 0..M::STEPS {
+        x = x + x * (one - a * x);
+    }
+    x
+}
+
+/// `e^w` of a complex `w`.
+fn exp<M: MultiDouble>(w: (M, M)) -> (M, M) {
+    let k = (w.0.leading() / std::f64::consts::LN_2).round();
+    let j = (w.1.leading() / (2.0 * std::f64::consts::PI)).round();
+    let halving = 2f64.powi(-EXP_HALVINGS);
+    let r = (
+        (w.0 - M::LN2 * M::constant(k)).scale(halving),
+        (w.1 - M::TWO_PI * M::constant(j)).scale(halving),
+    );
+    // e^r - 1 from the series, squared back up as (1 + s)^2 - 1 = s (2 + s)
+    // which keeps the small s accurate.
+    let mut term = r;
+    let mut s = r;
+    for n in 2..=M::TERMS {
+        let product = complex_mul(term, r);
+        let inverse = recip(M::constant(f64::from(n)));
+        term = (product.0 * inverse, product.1 * inverse);
+        s = (s.0 + term.0, s.1 + term.1);
+    }
+    for _ in 0..EXP_HALVINGS {
+        s = complex_mul(s, (s.0 + M::constant(2.0), s.1));
+    }
+    let factor = 2f64.powi(k as i32);
+    ((s.0 + M::constant(1.0)).scale(factor), s.1.scale(factor))
+}
+
+/// Principal `log z` of a nonzero `z`, by Newton's method on `e^w = z` from
+/// the `f64` value.
+fn log<M: MultiDouble>(z: (M, M)) -> (M, M) {
+    let (x, y) = (z.0.leading(), z.1.leading());
+    let mut w = (M::constant(x.hypot(y).ln()), M::constant(y.atan2(x)));
+    for _ in 0..M::STEPS {
+        let t = complex_mul(z, exp((-w.0, -w.1)));
+        w = (w.0 + t.0 - M::constant(1.0), w.1 + t.1);
+    }
+    w
+}
+
+/// `z^d = e^(d log z)` at the full precision of `M`.
+fn powf<M: MultiDouble>(z: (M, M), d: f64) -> (M, M) {
+    if z.0.leading() == 0.0 && z.1.leading() == 0.0 {
+        return (M::constant(0.0), M::constant(0.0));
+    }
+    let w = log(z);
+    let d = M::constant(d);
+    exp((w.0 * d, w.1 * d))
+}
+
+/// Unevaluated sum of two `f64`, ~106 bits of mantissa.
+#[derive(Debug, Copy, Clone)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+impl DoubleDouble {
+    pub fn new(hi: f64, lo: f64) -> DoubleDouble {
+        let (hi, lo) = quick_two_sum(hi, lo);
+        DoubleDouble { hi, lo }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn add(self, other: DoubleDouble) -> DoubleDouble {
+        let (s1, s2) = two_sum(self.hi, other.hi);
+        let (t1, t2) = two_sum(self.lo, other.lo);
+        let (s1, s2) = quick_two_sum(s1, s2 + t1);
+        DoubleDouble::new(s1, s2 + t2)
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn neg(self) -> DoubleDouble {
+        DoubleDouble {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn sub(self, other: DoubleDouble) -> DoubleDouble {
+        self + -other
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn mul(self, other: DoubleDouble) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, other.hi);
+        DoubleDouble::new(p, e + (self.hi * other.lo + self.lo * other.hi))
+    }
+}
+
+impl MultiDouble for DoubleDouble {
+    const STEPS: u32 = 1;
+    const TERMS: u32 = 10;
+    const LN2: DoubleDouble = DoubleDouble {
+        hi: std::f64::consts::LN_2,
+        lo: 2.319_046_813_846_299_6e-17,
+    };
+    const TWO_PI: DoubleDouble = DoubleDouble {
+        hi: 2.0 * std::f64::consts::PI,
+        lo: 2.449_293_598_294_706_4e-16,
+    };
+
+    fn leading(self) -> f64 {
+        self.hi
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        DoubleDouble {
+            hi: self.hi * factor,
+            lo: self.lo * factor,
+        }
+    }
+}
+
+impl Arithmetic for DoubleDouble {
+    fn constant(value: f64) -> Self {
+        DoubleDouble::from_f64(value)
+    }
+
+    fn abs(self) -> Self {
+        if self.hi < 0.0 {
+            -self
+        } else {
+            self
+        }
+    }
+
+    fn signum(self) -> Self {
+        DoubleDouble::from_f64(self.hi.signum())
+    }
+
+    fn powf(z: (Self, Self), d: f64) -> (Self, Self) {
+        powf(z, d)
+    }
+}
+
+impl Real for DoubleDouble {
+    fn from_float(value: &Float) -> Self {
+        let hi = value.to_f64();
+        let lo = Float::with_val(value.prec(), value - hi).to_f64();
+        DoubleDouble::new(hi, lo)
+    }
+
+    fn from_f64(value: f64) -> Self {
+        DoubleDouble { hi: value, lo: 0.0 }
+    }
+
+    fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+}
+
+/// Unevaluated sum of four `f64`, ~212 bits of mantissa.
+#[derive(Debug, Copy, Clone)]
+pub struct QuadDouble([f64; 4]);
+
+impl QuadDouble {
+    fn renormalize(c: [f64; 5]) -> QuadDouble {
+        let [c0, c1, c2, c3, c4] = c;
+        if !c0.is_finite() {
+            return QuadDouble([c0, c1, c2, c3]);
+        }
+        let (s0, c4) = quick_two_sum(c3, c4);
+        let (s0, c3) = quick_two_sum(c2, s0);
+        let (s0, c2) = quick_two_sum(c1, s0);
+        let (c0, c1) = quick_two_sum(c0, s0);
+
+        let mut s = [c0, c1, 0.0, 0.0];
+        let mut k = if c1 != 0.0 { 1 } else { 0 };
+        for &c in &[c2, c3, c4] {
+            let (hi, lo) = quick_two_sum(s[k], c);
+            s[k] = hi;
+            if lo != 0.0 && k < 3 {
+                k += 1;
+                s[k] = lo;
+            } else if k == 3 {
+                s[k] += lo;
+            }
+        }
+        QuadDouble(s)
+    }
+}
+
+impl Add for QuadDouble {
+    type Output = QuadDouble;
+
+    fn add(self, other: QuadDouble) -> QuadDouble {
+        let [a0, a1, a2, a3] = self.0;
+        let [b0, b1, b2, b3] = other.0;
+        let (s0, t0) = two_sum(a0, b0);
+        let (s1, t1) = two_sum(a1, b1);
+        let (s2, t2) = two_sum(a2, b2);
+        let (s3, t3) = two_sum(a3, b3);
+
+        let (s1, t0) = two_sum(s1, t0);
+        let (s2, t0, t1) = three_sum(s2, t0, t1);
+        let (s3, t0) = three_sum2(s3, t0, t2);
+        QuadDouble::renormalize([s0, s1, s2, s3, t0 + t1 + t3])
+    }
+}
+
+impl Neg for QuadDouble {
+    type Output = QuadDouble;
+
+    fn neg(self) -> QuadDouble {
+        let [a0, a1, a2, a3] = self.0;
+        QuadDouble([-a0, -a1, -a2, -a3])
+    }
+}
+
+impl Sub for QuadDouble {
+    type Output = QuadDouble;
+
+    fn sub(self, other: QuadDouble) -> QuadDouble {
+        self + -other
+    }
+}
+
+impl Mul for QuadDouble {
+    type Output = QuadDouble;
+
+    fn mul(self, other: QuadDouble) -> QuadDouble {
+        let [a0, a1, a2, a3] = self.0;
+        let [b0, b1, b2, b3] = other.0;
+
+        let (p0, q0) = two_prod(a0, b0);
+
+        let (p1, q1) = two_prod(a0, b1);
+        let (p2, q2) = two_prod(a1, b0);
+
+        let (p3, q3) = two_prod(a0, b2);
+        let (p4, q4) = two_prod(a1, b1);
+        let (p5, q5) = two_prod(a2, b0);
+
+        let (p1, p2, q0) = three_sum(p1, p2, q0);
+
+        let (p2, q1, q2) = three_sum(p2, q1, q2);
+        let (p3, p4, p5) = three_sum(p3, p4, p5);
+
+        let (s0, t0) = two_sum(p2, p3);
+        let (s1, t1) = two_sum(q1, p4);
+        let s2 = q2 + p5;
+        let (s1, t0) = two_sum(s1, t0);
+        let s2 = s2 + (t0 + t1);
+
+        let s1 = s1 + (a0 * b3 + a1 * b2 + a2 * b1 + a3 * b0 + q0 + q3 + q4 + q5);
+        QuadDouble::renormalize([p0, p1, s0, s1, s2])
+    }
+}
+
+impl MultiDouble for QuadDouble {
+    const STEPS: u32 = 2;
+    const TERMS: u32 = 18;
+    const LN2: QuadDouble = QuadDouble([
+        std::f64::consts::LN_2,
+        2.319_046_813_846_299_6e-17,
+        5.707_708_438_416_212e-34,
+        -3.582_432_210_601_811_4e-50,
+    ]);
+    const TWO_PI: QuadDouble = QuadDouble([
+        2.0 * std::f64::consts::PI,
+        2.449_293_598_294_706_4e-16,
+        -5.989_539_619_436_679e-33,
+        2.224_908_441_726_730_6e-49,
+    ]);
+
+    fn leading(self) -> f64 {
+        self.0[0]
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        let [a0, a1, a2, a3] = self.0;
+        QuadDouble([a0 * factor, a1 * factor, a2 * factor, a3 * factor])
+    }
+}
+
+impl Arithmetic for QuadDouble {
+    fn constant(value: f64) -> Self {
+        QuadDouble::from_f64(value)
+    }
+
+    fn abs(self) -> Self {
+        if self.0[0] < 0.0 {
+            -self
+        } else {
+            self
+        }
+    }
+
+    fn signum(self) -> Self {
+        QuadDouble::from_f64(self.0[0].signum())
+    }
+
+    fn powf(z: (Self, Self), d: f64) -> (Self, Self) {
+        powf(z, d)
+    }
+}
+
+impl Real for QuadDouble {
+    fn from_float(value: &Float) -> Self {
+        let mut rest = Float::with_val(value.prec(), value);
+        let mut limbs = [0.0; 4];
+        for limb in limbs.iter_mut() {
+            *limb = rest.to_f64();
+            rest -= *limb;
+        }
+        QuadDouble(limbs)
+    }
+
+    fn from_f64(value: f64) -> Self {
+        QuadDouble([value, 0.0, 0.0, 0.0])
+    }
+
+    fn to_f64(self) -> f64 {
+        self.0[0] + self.0[1]
+    }
+}
+
+fn two_sum_x4(a: f64x4, b: f64x4) -> (f64x4, f64x4) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+fn quick_two_sum_x4(a: f64x4, b: f64x4) -> (f64x4, f64x4) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+fn split_x4(a: f64x4) -> (f64x4, f64x4) {
+    let t = f64x4::splat(SPLITTER) * a;
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+fn two_prod_x4(a: f64x4, b: f64x4) -> (f64x4, f64x4) {
+    let p = a * b;
+    let (ah, al) = split_x4(a);
+    let (bh, bl) = split_x4(b);
+    (p, ((ah * bh - p) + ah * bl + al * bh) + al * bl)
+}
+
+/// Four double-double lanes packed into a pair of `f64x4`.
+#[derive(Debug, Copy, Clone)]
+pub struct DoubleDoublex4 {
+    hi: f64x4,
+    lo: f64x4,
+}
+
+impl DoubleDoublex4 {
+    fn new(hi: f64x4, lo: f64x4) -> DoubleDoublex4 {
+        let (hi, lo) = quick_two_sum_x4(hi, lo);
+        DoubleDoublex4 { hi, lo }
+    }
+}
+
+impl Add for DoubleDoublex4 {
+    type Output = DoubleDoublex4;
+
+    fn add(self, other: DoubleDoublex4) -> DoubleDoublex4 {
+        let (s1, s2) = two_sum_x4(self.hi, other.hi);
+        let (t1, t2) = two_sum_x4(self.lo, other.lo);
+        let (s1, s2) = quick_two_sum_x4(s1, s2 + t1);
+        DoubleDoublex4::new(s1, s2 + t2)
+    }
+}
+
+impl Neg for DoubleDoublex4 {
+    type Output = DoubleDoublex4;
+
+    fn neg(self) -> DoubleDoublex4 {
+        DoubleDoublex4 {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl Sub for DoubleDoublex4 {
+    type Output = DoubleDoublex4;
+
+    fn sub(self, other: DoubleDoublex4) -> DoubleDoublex4 {
+        self + -other
+    }
+}
+
+impl Mul for DoubleDoublex4 {
+    type Output = DoubleDoublex4;
+
+    fn mul(self, other: DoubleDoublex4) -> DoubleDoublex4 {
+        let (p, e) = two_prod_x4(self.hi, other.hi);
+        DoubleDoublex4::new(p, e + (self.hi * other.lo + self.lo * other.hi))
+    }
+}
+
+impl Arithmetic for DoubleDoublex4 {
+    fn constant(value: f64) -> Self {
+        DoubleDoublex4::splat(value)
+    }
+
+    fn abs(self) -> Self {
+        let negative = self.hi.lt(f64x4::splat(0.0));
+        DoubleDoublex4 {
+            hi: negative.select(-self.hi, self.hi),
+            lo: negative.select(-self.lo, self.lo),
+        }
+    }
+
+    fn signum(self) -> Self {
+        DoubleDoublex4 {
+            hi: Arithmetic::signum(self.hi),
+            lo: f64x4::splat(0.0),
+        }
+    }
+
+    fn powf(z: (Self, Self), d: f64) -> (Self, Self) {
+        let lane = |value: DoubleDoublex4, i: usize| DoubleDouble {
+            hi: value.hi.extract(i),
+            lo: value.lo.extract(i),
+        };
+        let lanes = (0..4)
+            .map(|i| powf((lane(z.0, i), lane(z.1, i)), d))
+            .collect::<Vec<(DoubleDouble, DoubleDouble)>>();
+        let pack = |limb: fn(&(DoubleDouble, DoubleDouble)) -> f64| {
+            f64x4::from_slice_unaligned(&lanes.iter().map(limb).collect::<Vec<f64>>())
+        };
+        (
+            DoubleDoublex4 {
+                hi: pack(|z| z.0.hi),
+                lo: pack(|z| z.0.lo),
+            },
+            DoubleDoublex4 {
+                hi: pack(|z| z.1.hi),
+                lo: pack(|z| z.1.lo),
+            },
+        )
+    }
+}
+
+impl SimdReal for DoubleDoublex4 {
+    fn from_floats(values: &[Float]) -> Self {
+        let mut hi = [0.0; 4];
+        let mut lo = [0.0; 4];
+        for (i, value) in values.iter().enumerate() {
+            let value = DoubleDouble::from_float(value);
+            hi[i] = value.hi;
+            lo[i] = value.lo;
+        }
+        DoubleDoublex4 {
+            hi: f64x4::from_slice_unaligned(&hi),
+            lo: f64x4::from_slice_unaligned(&lo),
+        }
+    }
+
+    fn splat(value: f64) -> Self {
+        DoubleDoublex4 {
+            hi: f64x4::splat(value),
+            lo: f64x4::splat(0.0),
+        }
+    }
+
+    fn to_f64x4(self) -> f64x4 {
+        self.hi + self.lo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{two_prod, two_sum, DoubleDouble, QuadDouble};
+    use mandelbrot::bounded::Real;
+    use mandelbrot::formula::Arithmetic;
+
+    // Exact value of `x` in units of 2^-110, for values small enough to fit
+    // and with no bits below that.
+    fn fixed(x: f64) -> i128 {
+        (x * 2f64.powi(110)) as i128
+    }
+
+    #[test]
+    fn two_sum_is_exact() {
+        let cases = [
+            (0.1, 0.2),
+            (3.0, 1e-17),
+            (-0.7, 0.300_000_000_000_000_04),
+            (1.0, 2f64.powi(-53)),
+        ];
+        for &(a, b) in &cases {
+            let (s, e) = two_sum(a, b);
+            assert_eq!(s, a + b);
+            assert_eq!(fixed(a) + fixed(b), fixed(s) + fixed(e));
+        }
+    }
+
+    #[test]
+    fn two_prod_is_exact() {
+        let cases = [
+            (0.1, 0.3),
+            (1.0 / 3.0, 3.0),
+            (123_456.789, 1e-7),
+            (-2.5e10, 7.1e-3),
+        ];
+        for &(a, b) in &cases {
+            let (p, e) = two_prod(a, b);
+            assert_eq!(p, a * b);
+            assert_eq!(e, a.mul_add(b, -p));
+        }
+    }
+
+    #[test]
+    fn renormalize_removes_overlap_and_gaps() {
+        let QuadDouble(limbs) = QuadDouble::renormalize([1.0, 1.0, 0.5, 0.0, 0.0]);
+        assert_eq!(limbs, [2.5, 0.0, 0.0, 0.0]);
+        let (a, b, c) = (2f64.powi(-60), 2f64.powi(-130), 2f64.powi(-200));
+        let QuadDouble(limbs) = QuadDouble::renormalize([1.0, 0.0, a, b, c]);
+        assert_eq!(limbs, [1.0, a, b, c]);
+        let QuadDouble(limbs) = QuadDouble::renormalize([1.0, 0.0, a, 0.0, b]);
+        assert_eq!(limbs, [1.0, a, b, 0.0]);
+    }
+
+    #[test]
+    fn keeps_bits_below_f64() {
+        let small = 2f64.powi(-60);
+        let square = DoubleDouble::new(1.0, small) * DoubleDouble::new(1.0, small);
+        assert_eq!((square.hi, square.lo), (1.0, 2.0 * small));
+        let z = QuadDouble([1.0, small, 0.0, 0.0]);
+        let QuadDouble(limbs) = z * z;
+        assert_eq!(limbs, [1.0, 2.0 * small, small * small, 0.0]);
+        let QuadDouble(limbs) =
+            QuadDouble([1.0, 2f64.powi(-150), 0.0, 0.0]) - QuadDouble::from_f64(1.0);
+        assert_eq!(limbs[0], 2f64.powi(-150));
+    }
+
+    #[test]
+    fn double_double_powf() {
+        let two = DoubleDouble::from_f64(2.0);
+        let zero = DoubleDouble::from_f64(0.0);
+        let (re, im) = DoubleDouble::powf((two, zero), 0.5);
+        assert!((re * re - two).to_f64().abs() < 1e-30);
+        assert!(im.to_f64().abs() < 1e-30);
+
+        let z = (DoubleDouble::from_f64(0.3), DoubleDouble::from_f64(-1.1));
+        let cube = (
+            z.0 * z.0 * z.0 - DoubleDouble::constant(3.0) * z.0 * z.1 * z.1,
+            DoubleDouble::constant(3.0) * z.0 * z.0 * z.1 - z.1 * z.1 * z.1,
+        );
+        let (re, im) = DoubleDouble::powf(z, 3.0);
+        assert!((re - cube.0).to_f64().abs() < 1e-29);
+        assert!((im - cube.1).to_f64().abs() < 1e-29);
+
+        let (re, im) = DoubleDouble::powf((DoubleDouble::from_f64(-1.0), zero), 0.5);
+        assert!(re.to_f64().abs() < 1e-30);
+        assert!((im - DoubleDouble::from_f64(1.0)).to_f64().abs() < 1e-30);
+
+        let (re, im) = DoubleDouble::powf((zero, zero), 2.5);
+        assert_eq!((re.to_f64(), im.to_f64()), (0.0, 0.0));
+    }
+
+    #[test]
+    fn quad_double_powf() {
+        let two = QuadDouble::from_f64(2.0);
+        let zero = QuadDouble::from_f64(0.0);
+        let (re, im) = QuadDouble::powf((two, zero), 0.5);
+        assert!((re * re - two).to_f64().abs() < 1e-60);
+        assert!(im.to_f64().abs() < 1e-60);
+
+        let z = (QuadDouble::from_f64(0.3), QuadDouble::from_f64(-1.1));
+        let cube = (
+            z.0 * z.0 * z.0 - QuadDouble::constant(3.0) * z.0 * z.1 * z.1,
+            QuadDouble::constant(3.0) * z.0 * z.0 * z.1 - z.1 * z.1 * z.1,
+        );
+        let (re, im) = QuadDouble::powf(z, 3.0);
+        assert!((re - cube.0).to_f64().abs() < 1e-58);
+        assert!((im - cube.1).to_f64().abs() < 1e-58);
+    }
+}
@@ -0,0 +1,315 @@
+use mandelbrot::expression::Expression;
+
+// A pixel belongs to a root once it is this close to it.
+const CONVERGENCE_TOLERANCE: f64 = 1e-6;
+// Roots are refined until no update moves them further than this.
+const ROOT_TOLERANCE: f64 = 1e-14;
+const ROOT_ITERATIONS: usize = 500;
+
+fn mul(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn div(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    let norm = b.0 * b.0 + b.1 * b.1;
+    (
+        (a.0 * b.0 + a.1 * b.1) / norm,
+        (a.1 * b.0 - a.0 * b.1) / norm,
+    )
+}
+
+fn sub(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn norm(a: (f64, f64)) -> f64 {
+    a.0 * a.0 + a.1 * a.1
+}
+
+/// Polynomial in `z` with complex coefficients, lowest power first.
+#[derive(Clone, Debug)]
+pub struct Polynomial {
+    coefficients: Vec<(f64, f64)>,
+}
+
+impl Polynomial {
+    /// Parses a polynomial written in the formula language, such as
+    /// `z^3 - 1`.
+    pub fn parse(source: &str) -> Result<Polynomial, String> {
+        let mut coefficients = Self::coefficients(&Expression::parse(source)?)?;
+        while coefficients.len() > 1 && coefficients[coefficients.len() - 1] == (0.0, 0.0) {
+            coefficients.pop();
+        }
+        if coefficients.len() < 3 {
+            return Err(String::from("the polynomial needs a degree of at least 2"));
+        }
+        Ok(Polynomial { coefficients })
+    }
+
+    fn coefficients(expression: &Expression) -> Result<Vec<(f64, f64)>, String> {
+        match expression {
+            Expression::Z => Ok(vec![(0.0, 0.0), (1.0, 0.0)]),
+            Expression::Constant(re, im) => Ok(vec![(*re, *im)]),
+            Expression::Add(a, b) => Ok(add(&Self::coefficients(a)?, &Self::coefficients(b)?)),
+            Expression::Sub(a, b) => {
+                let b = negate(&Self::coefficients(b)?);
+                Ok(add(&Self::coefficients(a)?, &b))
+            }
+            Expression::Mul(a, b) => Ok(multiply(&Self::coefficients(a)?, &Self::coefficients(b)?)),
+            Expression::Div(a, b) => match Self::coefficients(b)?.as_slice() {
+                [divisor] => Ok(Self::coefficients(a)?
+                    .iter()
+                    .map(|&coefficient| div(coefficient, *divisor))
+                    .collect()),
+                _ => Err(String::from("only division by a constant is allowed")),
+            },
+            Expression::Neg(a) => Ok(negate(&Self::coefficients(a)?)),
+            Expression::Power(a, exponent) => {
+                if exponent.fract() != 0.0 || *exponent < 0.0 {
+                    return Err(String::from("powers have to be positive integers"));
+                }
+                let a = Self::coefficients(a)?;
+                Ok((0..*exponent as u32).fold(vec![(1.0, 0.0)], |power, _| multiply(&power, &a)))
+            }
+            Expression::C => Err(String::from("c is not allowed in a polynomial")),
+            Expression::Call(..) => Err(String::from("functions are not allowed in a polynomial")),
+        }
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+
+    /// `p(z)` and `p'(z)` by Horner's method.
+    fn evaluate(&self, z: (f64, f64)) -> ((f64, f64), (f64, f64)) {
+        let mut value = (0.0, 0.0);
+        let mut derivative = (0.0, 0.0);
+        for &coefficient in self.coefficients.iter().rev() {
+            let product = mul(derivative, z);
+            derivative = (product.0 + value.0, product.1 + value.1);
+            let product = mul(value, z);
+            value = (product.0 + coefficient.0, product.1 + coefficient.1);
+        }
+        (value, derivative)
+    }
+
+    /// All roots at once with the Durand-Kerner method.
+    pub fn roots(&self) -> Vec<(f64, f64)> {
+        let leading = self.coefficients[self.degree()];
+        let monic = Polynomial {
+            coefficients: self
+                .coefficients
+                .iter()
+                .map(|&coefficient| div(coefficient, leading))
+                .collect(),
+        };
+        let seed = (0.4, 0.9);
+        let mut roots = Vec::with_capacity(self.degree());
+        roots.push((1.0, 0.0));
+        for i in 1..self.degree() {
+            let root = mul(roots[i - 1], seed);
+            roots.push(root);
+        }
+        for _ in 0..ROOT_ITERATIONS {
+            let mut change: f64 = 0.0;
+            for i in 0..roots.len() {
+                let mut denominator = (1.0, 0.0);
+                for (j, &other) in roots.iter().enumerate() {
+                    if i != j {
+                        denominator = mul(denominator, sub(roots[i], other));
+                    }
+                }
+                let delta = div(monic.evaluate(roots[i]).0, denominator);
+                roots[i] = sub(roots[i], delta);
+                change = change.max(norm(delta));
+            }
+            if change < ROOT_TOLERANCE * ROOT_TOLERANCE {
+                break;
+            }
+        }
+        roots
+    }
+}
+
+fn add(a: &[(f64, f64)], b: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    (0..a.len().max(b.len()))
+        .map(|i| {
+            let a = a.get(i).cloned().unwrap_or((0.0, 0.0));
+            let b = b.get(i).cloned().unwrap_or((0.0, 0.0));
+            (a.0 + b.0, a.1 + b.1)
+        })
+        .collect()
+}
+
+fn negate(a: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    a.iter().map(|&(re, im)| (-re, -im)).collect()
+}
+
+fn multiply(a: &[(f64, f64)], b: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mut product = vec![(0.0, 0.0); a.len() + b.len() - 1];
+    for (i, &a) in a.iter().enumerate() {
+        for (j, &b) in b.iter().enumerate() {
+            let term = mul(a, b);
+            product[i + j].0 += term.0;
+            product[i + j].1 += term.1;
+        }
+    }
+    product
+}
+
+/// Outcome of Newton's method for one pixel.
+#[derive(Clone, Copy, Debug)]
+pub enum Basin {
+    /// Index of the root the pixel converged to, with the iterations it took.
+    Converged {
+        root: usize,
+        iter: u64,
+    },
+    Diverged,
+}
+
+/// Newton's method `z -> z - p(z) / p'(z)` for a polynomial with known roots.
+pub struct Newton {
+    polynomial: Polynomial,
+    roots: Vec<(f64, f64)>,
+}
+
+impl Newton {
+    pub fn new(polynomial: Polynomial) -> Newton {
+        Newton {
+            roots: polynomial.roots(),
+            polynomial,
+        }
+    }
+
+    pub fn get_roots(&self) -> &[(f64, f64)] {
+        &self.roots
+    }
+
+    pub fn basin(&self, mut z: (f64, f64), limit: u64) -> Basin {
+        let tolerance = CONVERGENCE_TOLERANCE * CONVERGENCE_TOLERANCE;
+        for iter in 0..limit {
+            if let Some(root) = self
+                .roots
+                .iter()
+                .position(|&root| norm(sub(z, root)) < tolerance)
+            {
+                return Basin::Converged { root, iter };
+            }
+            let (value, derivative) = self.polynomial.evaluate(z);
+            if derivative == (0.0, 0.0) {
+                break;
+            }
+            z = sub(z, div(value, derivative));
+            if z.0.is_nan() || z.1.is_nan() {
+                break;
+            }
+        }
+        Basin::Diverged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{norm, sub, Basin, Newton, Polynomial};
+
+    fn coefficients(source: &str) -> Vec<(f64, f64)> {
+        Polynomial::parse(source).unwrap().coefficients
+    }
+
+    // Every expected root is found exactly once.
+    fn assert_roots(source: &str, expected: &[(f64, f64)]) {
+        let polynomial = Polynomial::parse(source).unwrap();
+        let mut roots = polynomial.roots();
+        assert_eq!(roots.len(), expected.len());
+        for &root in expected {
+            let found = roots
+                .iter()
+                .position(|&other| norm(sub(root, other)) < 1e-20)
+                .unwrap_or_else(|| panic!("{:?} is not a root of {} in {:?}", root, source, roots));
+            roots.remove(found);
+        }
+    }
+
+    #[test]
+    fn parses_coefficients() {
+        let zero = (0.0, 0.0);
+        assert_eq!(
+            coefficients("z^3 - 1"),
+            vec![(-1.0, 0.0), zero, zero, (1.0, 0.0)]
+        );
+        assert_eq!(
+            coefficients("(z - 1) * (z + 2) / 2"),
+            vec![(-1.0, 0.0), (0.5, 0.0), (0.5, 0.0)]
+        );
+        assert_eq!(
+            coefficients("2i*z^2 + z"),
+            vec![zero, (1.0, 0.0), (0.0, 2.0)]
+        );
+        assert_eq!(
+            coefficients("z = z^4 - z^4 + z^2 - 1"),
+            vec![(-1.0, 0.0), zero, (1.0, 0.0)]
+        );
+        assert_eq!(Polynomial::parse("z^(5-2)").unwrap().degree(), 3);
+    }
+
+    #[test]
+    fn rejects_non_polynomials() {
+        let error = |source: &str| Polynomial::parse(source).unwrap_err();
+        let degree = "the polynomial needs a degree of at least 2";
+        assert_eq!(error("z + 1"), degree);
+        assert_eq!(error("z^2 - z^2 + 3"), degree);
+        assert_eq!(error("c*z^2"), "c is not allowed in a polynomial");
+        assert_eq!(
+            error("conj(z)^2"),
+            "functions are not allowed in a polynomial"
+        );
+        assert_eq!(error("z^3 / z"), "only division by a constant is allowed");
+        assert_eq!(error("z^-2"), "powers have to be positive integers");
+        assert_eq!(error("z^2.5"), "powers have to be positive integers");
+        assert_eq!(error("z^"), "unexpected end of formula at 2");
+    }
+
+    #[test]
+    fn finds_the_roots() {
+        let half = 3f64.sqrt() / 2.0;
+        assert_roots("z^3 - 1", &[(1.0, 0.0), (-0.5, half), (-0.5, -half)]);
+        assert_roots(
+            "(z - 1) * (z + 2) * (z - 3i)",
+            &[(1.0, 0.0), (-2.0, 0.0), (0.0, 3.0)],
+        );
+        assert_roots("2*z^2 + 8", &[(0.0, 2.0), (0.0, -2.0)]);
+        assert_roots(
+            "z^4 - 1",
+            &[(1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)],
+        );
+    }
+
+    #[test]
+    fn converges_to_the_nearest_root() {
+        let newton = Newton::new(Polynomial::parse("z^2 - 1").unwrap());
+        let index = |root: (f64, f64)| {
+            newton
+                .get_roots()
+                .iter()
+                .position(|&other| norm(sub(root, other)) < 1e-20)
+                .unwrap()
+        };
+        match newton.basin((2.0, 0.5), 100) {
+            Basin::Converged { root, iter } => {
+                assert_eq!(root, index((1.0, 0.0)));
+                assert!(iter > 0);
+            }
+            Basin::Diverged => panic!("(2, 0.5) diverged"),
+        }
+        match newton.basin((-0.5, -3.0), 100) {
+            Basin::Converged { root, .. } => assert_eq!(root, index((-1.0, 0.0))),
+            Basin::Diverged => panic!("(-0.5, -3) diverged"),
+        }
+        // p'(0) = 0 on the boundary between the basins.
+        match newton.basin((0.0, 0.0), 100) {
+            Basin::Diverged => {}
+            basin => panic!("0 converged: {:?}", basin),
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::marker::PhantomData;
+use std::ops::{Add, Mul, Sub};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rug::{Complex, Float};
+
+use mandelbrot::average::OrbitAverages;
+use mandelbrot::bounded::{
+    main_component, Bound, BoundsChecker, BoundsSettings, Escape, Periodicity, Pixel,
+    PERIOD_TOLERANCE,
+};
+use mandelbrot::compute::{CancelToken, ComputeSettings, Plane};
+use mandelbrot::floatexp::FloatExp;
+use mandelbrot::formula::Formula;
+use mandelbrot::interior::Interior;
+use mandelbrot::trap::TrapDistance;
+
+pub trait Delta:
+    Copy
+    + Send
+    + Sync
+    + 'static
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+{
+    fn from_float(value: &Float) -> Self;
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn to_floatexp(self) -> FloatExp;
+}
+
+impl Delta for f64 {
+    fn from_float(value: &Float) -> Self {
+        value.to_f64()
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn to_floatexp(self) -> FloatExp {
+        FloatExp::new(self, 0)
+    }
+}
+
+fn complex_mul<D: Delta>(a: (D, D), b: (D, D)) -> (D, D) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn complex_norm<D: Delta>(a: (D, D)) -> D {
+    a.0 * a.0 + a.1 * a.1
+}
+
+// Pauldelbrot criterion, squared: a pixel has lost precision once
+// |Z + dz| < 1e-3 |Z|.
+const GLITCH_TOLERANCE: f64 = 1e-6;
+
+// Orbit values are compared in f64 for periodicity checking, which can only
+// resolve differences well above its rounding error for |z| <= 2. Deeper
+// views iterate bounded pixels up to the limit instead.
+const PERIOD_MIN_EPSILON: f64 = 1e-12;
+
+// The reference orbit grows as it is computed past this many values, so a
+// large iteration limit does not reserve memory it may never use.
+const ORBIT_CAPACITY: u64 = 1 << 16;
+
+/// Coefficients of `dz_n = A dc + B dc^2 + C dc^3`, valid for every pixel of
+/// the view up to iteration `skip`.
+pub struct SeriesApproximation<D: Delta> {
+    skip: usize,
+    coefficients: [(D, D); 3],
+}
+
+impl<D: Delta> SeriesApproximation<D> {
+    pub fn new(orbit: &[(f64, f64)], radius: D, tolerance: f64) -> SeriesApproximation<D> {
+        let zero = (D::from_f64(0.0), D::from_f64(0.0));
+        let two = D::from_f64(2.0);
+        // The stop test is done in FloatExp: with f64 deltas the fourth power
+        // of a deep radius underflows and the norms of large coefficients
+        // overflow.
+        let norm = |(re, im): (D, D)| complex_norm((re.to_floatexp(), im.to_floatexp()));
+        let radius = radius.to_floatexp() * radius.to_floatexp();
+        let radius = radius * radius;
+        let tolerance = FloatExp::new(tolerance * tolerance, 0);
+        let mut coefficients = [zero; 3];
+        let mut skip = 0;
+        // Stop before the last orbit value so pixels always have a reference
+        // to continue iterating from.
+        while skip + 2 < orbit.len() {
+            let z = (D::from_f64(orbit[skip].0), D::from_f64(orbit[skip].1));
+            let z = (two * z.0, two * z.1);
+            let [a, b, c] = coefficients;
+            let az = complex_mul(z, a);
+            let bz = complex_mul(z, b);
+            let cz = complex_mul(z, c);
+            let aa = complex_mul(a, a);
+            let ab = complex_mul(a, b);
+            let next = [
+                (az.0 + D::from_f64(1.0), az.1),
+                (bz.0 + aa.0, bz.1 + aa.1),
+                (cz.0 + two * ab.0, cz.1 + two * ab.1),
+            ];
+            // The cubic term has to stay negligible next to the linear one
+            // for the largest |dc| in the view. Coefficients that overflowed
+            // compare as NaN and stop the series too.
+            let error = norm(next[2]) * radius;
+            if error.partial_cmp(&(tolerance * norm(next[0]))) != Some(std::cmp::Ordering::Less) {
+                break;
+            }
+            coefficients = next;
+            skip += 1;
+        }
+        SeriesApproximation { skip, coefficients }
+    }
+
+    /// Returns `dz` at iteration `skip` together with its derivative
+    /// `A + 2 B dc + 3 C dc^2` with respect to `dc`.
+    fn evaluate(&self, dc: (D, D)) -> ((D, D), (D, D)) {
+        let [a, b, c] = self.coefficients;
+        let two = D::from_f64(2.0);
+        let three = D::from_f64(3.0);
+        let dc2 = complex_mul(dc, dc);
+        let dc3 = complex_mul(dc2, dc);
+        let bdc = complex_mul(b, dc);
+        let cdc2 = complex_mul(c, dc2);
+        let value = {
+            let a = complex_mul(a, dc);
+            let b = complex_mul(b, dc2);
+            let c = complex_mul(c, dc3);
+            (a.0 + b.0 + c.0, a.1 + b.1 + c.1)
+        };
+        let derivative = (
+            a.0 + two * bdc.0 + three * cdc2.0,
+            a.1 + two * bdc.1 + three * cdc2.1,
+        );
+        (value, derivative)
+    }
+}
+
+/// Orbit of the view center, computed once at full precision and stored as
+/// `f64` since every value of a bounded orbit lies within `|z| <= 2`.
+pub struct ReferenceOrbit<D: Delta> {
+    x: Float,
+    y: Float,
+    orbit: Vec<(f64, f64)>,
+    series: SeriesApproximation<D>,
+    glitches: AtomicU64,
+}
+
+impl<D: Delta> ReferenceOrbit<D> {
+    /// A cancelled orbit stops short, the pixels are not computed then.
+    pub fn new(
+        x: &Float,
+        y: &Float,
+        radius: &Float,
+        settings: BoundsSettings,
+        cancel: &CancelToken,
+    ) -> ReferenceOrbit<D> {
+        let precision = settings.precision;
+        let c = Complex::with_val(precision, (x, y));
+        let mut z = Complex::new(precision);
+        let bailout = settings.bailout();
+        let mut orbit = Vec::with_capacity(settings.limit.min(ORBIT_CAPACITY) as usize + 1);
+        orbit.push((0.0, 0.0));
+        for _ in 0..settings.limit {
+            if cancel.is_cancelled() {
+                break;
+            }
+            z.square_mut();
+            z += &c;
+            let zz = (z.real().to_f64(), z.imag().to_f64());
+            orbit.push(zz);
+            if settings.bailout_norm.apply(zz.0, zz.1) >= bailout {
+                break;
+            }
+        }
+        let series =
+            SeriesApproximation::new(&orbit, D::from_float(radius), settings.series_tolerance);
+        ReferenceOrbit {
+            x: Float::with_val(precision, x),
+            y: Float::with_val(precision, y),
+            orbit,
+            series,
+            glitches: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Iterates each pixel as a low precision offset `dz` from the reference orbit
+/// `Z` using `dz' = 2 Z dz + dz^2 + dc`.
+pub struct Perturbation<D: Delta> {
+    _delta: PhantomData<D>,
+}
+
+impl<D: Delta> Perturbation<D> {
+    fn check_pixel(
+        reference: &ReferenceOrbit<D>,
+        c: (f64, f64),
+        dc: (D, D),
+        step: D,
+        settings: BoundsSettings,
+    ) -> Pixel {
+        let mut trap = TrapDistance::new(settings.trap);
+        let mut averages = OrbitAverages::new(settings);
+        let two = D::from_f64(2.0);
+        let bailout = settings.bailout();
+        let (mut dz, mut derivative) = reference.series.evaluate(dc);
+        let mut n = reference.series.skip;
+        let mut iter = n as u64;
+        let mut glitched = false;
+        let epsilon = step.to_f64() * PERIOD_TOLERANCE;
+        let periodic = epsilon > PERIOD_MIN_EPSILON;
+        let mut saved = {
+            let (zr, zi) = reference.orbit[n];
+            (zr + dz.0.to_f64(), zi + dz.1.to_f64())
+        };
+        let mut periodicity = Periodicity::new();
+        while iter < settings.limit {
+            let (zr, zi) = reference.orbit[n];
+            let z = (D::from_f64(zr), D::from_f64(zi));
+            let full = (z.0 + dz.0, z.1 + dz.1);
+            derivative = (
+                two * (full.0 * derivative.0 - full.1 * derivative.1) + D::from_f64(1.0),
+                two * (full.0 * derivative.1 + full.1 * derivative.0),
+            );
+            dz = (
+                two * (z.0 * dz.0 - z.1 * dz.1) + dz.0 * dz.0 - dz.1 * dz.1 + dc.0,
+                two * (z.0 * dz.1 + z.1 * dz.0) + two * dz.0 * dz.1 + dc.1,
+            );
+            n += 1;
+            let (zr, zi) = reference.orbit[n];
+            let z = (zr + dz.0.to_f64(), zi + dz.1.to_f64());
+            trap.update(z);
+            averages.update(z, c);
+            let norm = settings.bailout_norm.apply(z.0, z.1);
+            if norm < bailout {
+                iter += 1;
+            } else {
+                let derivative = (
+                    (derivative.0 * step).to_f64(),
+                    (derivative.1 * step).to_f64(),
+                );
+                let escape = Escape::new(iter, norm, z, derivative, 2.0, settings);
+                return trap
+                    .pixel(Bound::Unbounded(escape))
+                    .with_averages(&averages);
+            }
+            if periodic {
+                if (z.0 - saved.0).abs() < epsilon && (z.1 - saved.1).abs() < epsilon {
+                    let period = periodicity.period();
+                    let interior =
+                        Interior::analyze(c, z, period, step.to_f64(), &Plane::Mandelbrot);
+                    return trap.pixel(Bound::Bounded(interior));
+                }
+                if periodicity.advance() {
+                    saved = z;
+                }
+            }
+            // Rebase onto the start of the reference orbit (where Z = 0) when
+            // the pixel glitches or outlives the reference.
+            let glitch = z.0 * z.0 + z.1 * z.1 < GLITCH_TOLERANCE * (zr * zr + zi * zi);
+            if glitch || n + 1 >= reference.orbit.len() {
+                if glitch && !glitched {
+                    glitched = true;
+                    reference.glitches.fetch_add(1, Ordering::Relaxed);
+                }
+                dz = (D::from_f64(zr) + dz.0, D::from_f64(zi) + dz.1);
+                n = 0;
+            }
+        }
+        trap.pixel(Bound::Bounded(None))
+    }
+}
+
+impl<D: Delta> BoundsChecker for Perturbation<D> {
+    type Context = ReferenceOrbit<D>;
+
+    fn prepare(settings: &ComputeSettings) -> ReferenceOrbit<D> {
+        let (x, y) = settings.get_position();
+        let (width, height) = settings.get_size();
+        let mut bounds = settings.get_bounds();
+        // Iterations skipped by the series approximation are not seen by the
+        // orbit trap or the orbit averages.
+        if bounds.trap.is_some() || bounds.stripe_density.is_some() {
+            bounds.series_tolerance = 0.0;
+        }
+        let ratio = f64::from(width) / f64::from(height);
+        let radius = Float::with_val(bounds.precision, settings.get_scale())
+            * ((ratio * ratio + 1.0).sqrt() / 2.0);
+        ReferenceOrbit::new(x, y, &radius, bounds, settings.get_cancel())
+    }
+
+    // Only reached for the quadratic formula, `compute_set` routes the others
+    // to MPC.
+    fn check_bounded<F: Formula>(
+        context: &ReferenceOrbit<D>,
+        _formula: F,
+        x: &[Float],
+        y: &[Float],
+        step: &Float,
+        settings: BoundsSettings,
+        out: &mut [Pixel],
+    ) {
+        let cf = (x[0].to_f64(), y[0].to_f64());
+        if let (Some(period), None) = (main_component(cf.0, cf.1), settings.trap) {
+            let interior =
+                Interior::analyze(cf, (0.0, 0.0), period, step.to_f64(), &Plane::Mandelbrot);
+            out[0] = Pixel::new(Bound::Bounded(interior), std::f64::INFINITY);
+            return;
+        }
+        let precision = settings.precision;
+        let dc = (
+            Float::with_val(precision, &x[0] - &context.x),
+            Float::with_val(precision, &y[0] - &context.y),
+        );
+        out[0] = Self::check_pixel(
+            context,
+            cf,
+            (D::from_float(&dc.0), D::from_float(&dc.1)),
+            D::from_float(step),
+            settings,
+        );
+    }
+
+    fn glitches(context: &ReferenceOrbit<D>) -> u64 {
+        context.glitches.load(Ordering::Relaxed)
+    }
+
+    fn mask() -> Vec<usize> {
+        vec![0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{complex_mul, SeriesApproximation};
+
+    // At this radius the fourth power underflows f64, the series has to stop
+    // on its own before the end of the orbit.
+    #[test]
+    fn series_stops_at_depth_with_f64_deltas() {
+        let radius = 1e-100;
+        // Orbit of the Misiurewicz point `i`, whose coefficients grow
+        // exponentially.
+        let mut orbit = vec![(0.0, 0.0), (0.0, 1.0)];
+        while orbit.len() < 1001 {
+            orbit.push(if orbit.len() % 2 == 0 {
+                (-1.0, 1.0)
+            } else {
+                (0.0, -1.0)
+            });
+        }
+        let series = SeriesApproximation::<f64>::new(&orbit, radius, 1e-6);
+        assert!(series.skip > 100);
+        assert!(series.skip + 2 < orbit.len());
+
+        let dc = (radius / 2.0, radius / 3.0);
+        let mut dz = (0.0, 0.0);
+        for z in &orbit[..series.skip] {
+            let zdz = complex_mul((2.0 * z.0, 2.0 * z.1), dz);
+            let dz2 = complex_mul(dz, dz);
+            dz = (zdz.0 + dz2.0 + dc.0, zdz.1 + dz2.1 + dc.1);
+        }
+        let (value, _) = series.evaluate(dc);
+        let error = (value.0 - dz.0).hypot(value.1 - dz.1);
+        assert!(error < 1e-6 * dz.0.hypot(dz.1));
+    }
+}
@@ -0,0 +1,112 @@
+// Side of the square tiles, a multiple of every pass stride so passes line up
+// with tile borders.
+pub const TILE_SIZE: u32 = 64;
+
+/// A rectangle of the image in pixels, only smaller than `TILE_SIZE` at the
+/// right and bottom edges.
+#[derive(Clone, Copy, Debug)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    pub fn area(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    /// Index into the image of `(x, y)` within the tile.
+    pub fn index(&self, x: u32, y: u32, image_width: u32) -> usize {
+        (self.y + y) as usize * image_width as usize + (self.x + x) as usize
+    }
+}
+
+/// Tiles covering the image in rings around the tile containing `focus`,
+/// going around each ring by angle.
+pub fn spiral(width: u32, height: u32, focus: (u32, u32)) -> Vec<Tile> {
+    let center = (
+        i64::from(focus.0.min(width.saturating_sub(1)) / TILE_SIZE),
+        i64::from(focus.1.min(height.saturating_sub(1)) / TILE_SIZE),
+    );
+    let mut tiles = (0..height)
+        .step_by(TILE_SIZE as usize)
+        .flat_map(|y| (0..width).step_by(TILE_SIZE as usize).map(move |x| (x, y)))
+        .map(|(x, y)| Tile {
+            x,
+            y,
+            width: TILE_SIZE.min(width - x),
+            height: TILE_SIZE.min(height - y),
+        })
+        .collect::<Vec<Tile>>();
+    tiles.sort_by(|a, b| {
+        let key = |tile: &Tile| {
+            let dx = i64::from(tile.x / TILE_SIZE) - center.0;
+            let dy = i64::from(tile.y / TILE_SIZE) - center.1;
+            let angle = (dy as f64).atan2(dx as f64);
+            (dx.abs().max(dy.abs()), angle)
+        };
+        let (ring_a, angle_a) = key(a);
+        let (ring_b, angle_b) = key(b);
+        ring_a
+            .cmp(&ring_b)
+            .then(angle_a.partial_cmp(&angle_b).unwrap())
+    });
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{spiral, Tile, TILE_SIZE};
+
+    fn contains(tile: &Tile, (x, y): (u32, u32)) -> bool {
+        x >= tile.x && y >= tile.y && x < tile.x + tile.width && y < tile.y + tile.height
+    }
+
+    // Chebyshev distance in tiles from the tile containing `focus`.
+    fn ring(tile: &Tile, focus: (u32, u32)) -> i64 {
+        let distance = |a: u32, b: u32| (i64::from(a / TILE_SIZE) - i64::from(b / TILE_SIZE)).abs();
+        distance(tile.x, focus.0).max(distance(tile.y, focus.1))
+    }
+
+    #[test]
+    fn starts_at_the_focus_and_goes_outwards() {
+        for &focus in &[(0, 0), (100, 70), (299, 199), (150, 10)] {
+            let tiles = spiral(300, 200, focus);
+            assert!(contains(&tiles[0], focus), "{:?} {:?}", focus, tiles[0]);
+            let rings = tiles
+                .iter()
+                .map(|tile| ring(tile, focus))
+                .collect::<Vec<i64>>();
+            assert!(
+                rings.windows(2).all(|pair| pair[0] <= pair[1]),
+                "{:?}",
+                rings
+            );
+        }
+    }
+
+    // Edge tiles are cut to the image, which need not be a multiple of the
+    // tile size, and a focus outside of it starts from the nearest tile.
+    #[test]
+    fn covers_the_image_once() {
+        for &(width, height) in &[(300, 200), (64, 64), (1, 1), (65, 127), (130, 3)] {
+            let mut covered = vec![0; (width * height) as usize];
+            for tile in spiral(width, height, (width / 3, height)) {
+                assert!(tile.width > 0 && tile.height > 0);
+                for y in 0..tile.height {
+                    for x in 0..tile.width {
+                        covered[tile.index(x, y, width)] += 1;
+                    }
+                }
+            }
+            assert!(
+                covered.iter().all(|&count| count == 1),
+                "{}x{}",
+                width,
+                height
+            );
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use packed_simd::f64x4;
+
+use mandelbrot::bounded::{Bound, Pixel};
+
+/// Shapes the distance of the orbit is measured to for orbit trap colouring.
+#[derive(Clone, Copy, Debug)]
+pub enum Trap {
+    Point(f64, f64),
+    /// The line through a point at an angle in radians.
+    Line(f64, f64, f64),
+    /// The horizontal and vertical line through a point.
+    Cross(f64, f64),
+    /// A circle around a point with a radius.
+    Circle(f64, f64, f64),
+    /// Lines at every multiple of the spacing along both axes.
+    Grid(f64),
+}
+
+impl Trap {
+    pub fn to_int(self) -> i32 {
+        match self {
+            Self::Point(..) => 0,
+            Self::Line(..) => 1,
+            Self::Cross(..) => 2,
+            Self::Circle(..) => 3,
+            Self::Grid(..) => 4,
+        }
+    }
+
+    pub fn from_int(value: i32) -> Self {
+        match value {
+            0 => Self::Point(0.0, 0.0),
+            1 => Self::Line(0.0, 0.0, 0.0),
+            2 => Self::Cross(0.0, 0.0),
+            3 => Self::Circle(0.0, 0.0, 1.0),
+            4 => Self::Grid(0.5),
+            _ => Self::Point(0.0, 0.0),
+        }
+    }
+
+    pub fn distance(self, re: f64, im: f64) -> f64 {
+        match self {
+            Self::Point(x, y) => (re - x).hypot(im - y),
+            Self::Line(x, y, angle) => {
+                let (sin, cos) = angle.sin_cos();
+                ((re - x) * sin - (im - y) * cos).abs()
+            }
+            Self::Cross(x, y) => (re - x).abs().min((im - y).abs()),
+            Self::Circle(x, y, radius) => ((re - x).hypot(im - y) - radius).abs(),
+            Self::Grid(spacing) => {
+                let offset = |value: f64| (value - (value / spacing).round() * spacing).abs();
+                offset(re).min(offset(im))
+            }
+        }
+    }
+
+    pub fn distance_x4(self, re: f64x4, im: f64x4) -> f64x4 {
+        let mut distances = [0.0; 4];
+        for (i, distance) in distances.iter_mut().enumerate() {
+            *distance = self.distance(re.extract(i), im.extract(i));
+        }
+        f64x4::from_slice_unaligned(&distances)
+    }
+}
+
+/// Running minimum of the distance of an orbit to the trap, if there is one.
+pub struct TrapDistance {
+    trap: Option<Trap>,
+    distance: f64,
+}
+
+impl TrapDistance {
+    pub fn new(trap: Option<Trap>) -> TrapDistance {
+        TrapDistance {
+            trap,
+            distance: std::f64::INFINITY,
+        }
+    }
+
+    pub fn update(&mut self, z: (f64, f64)) {
+        if let Some(trap) = self.trap {
+            self.distance = self.distance.min(trap.distance(z.0, z.1));
+        }
+    }
+
+    pub fn pixel(&self, bound: Bound) -> Pixel {
+        Pixel::new(bound, self.distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_4;
+
+    use super::{Trap, TrapDistance};
+    use mandelbrot::bounded::Bound;
+
+    fn assert_distance(trap: Trap, z: (f64, f64), expected: f64) {
+        let distance = trap.distance(z.0, z.1);
+        assert!(
+            (distance - expected).abs() < 1e-12,
+            "{:?} {:?} {}",
+            trap,
+            z,
+            distance
+        );
+    }
+
+    #[test]
+    fn point_distance() {
+        let trap = Trap::Point(1.0, -1.0);
+        assert_distance(trap, (1.0, -1.0), 0.0);
+        assert_distance(trap, (4.0, 3.0), 5.0);
+    }
+
+    #[test]
+    fn line_distance() {
+        let trap = Trap::Line(0.0, 1.0, FRAC_PI_4);
+        assert_distance(trap, (2.0, 3.0), 0.0);
+        assert_distance(trap, (1.0, 0.0), 2f64.sqrt());
+        assert_distance(Trap::Line(0.0, 0.5, 0.0), (-7.0, 2.0), 1.5);
+    }
+
+    #[test]
+    fn cross_distance() {
+        let trap = Trap::Cross(1.0, 2.0);
+        assert_distance(trap, (1.0, 10.0), 0.0);
+        assert_distance(trap, (1.5, 5.0), 0.5);
+        assert_distance(trap, (-3.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn circle_distance() {
+        let trap = Trap::Circle(1.0, 0.0, 2.0);
+        assert_distance(trap, (1.0, 2.0), 0.0);
+        assert_distance(trap, (1.0, 0.0), 2.0);
+        assert_distance(trap, (4.0, 4.0), 3.0);
+    }
+
+    #[test]
+    fn grid_distance() {
+        let trap = Trap::Grid(0.5);
+        assert_distance(trap, (1.5, 0.3), 0.0);
+        assert_distance(trap, (0.2, 0.35), 0.15);
+        assert_distance(trap, (-0.9, -0.6), 0.1);
+    }
+
+    #[test]
+    fn keeps_the_smallest_distance_of_the_orbit() {
+        let mut trap = TrapDistance::new(Some(Trap::Point(0.0, 0.0)));
+        for &z in &[(3.0, 4.0), (0.6, 0.8), (0.0, 2.0)] {
+            trap.update(z);
+        }
+        assert!((trap.pixel(Bound::Bounded(None)).trap - 1.0).abs() < 1e-6);
+        let mut none = TrapDistance::new(None);
+        none.update((0.0, 0.0));
+        assert!(none.pixel(Bound::Bounded(None)).trap.is_infinite());
+    }
+}
@@ -5,39 +5,172 @@ use std::thread;
 
 use rug::Float;
 use threadpool::ThreadPool;
+use time::precise_time_ns;
 
 use glium::{
     glutin::{self, ElementState, Event, MouseButton, WindowEvent},
     Display, Surface,
 };
-use imgui::{Condition, Context, FontConfig, FontGlyphRanges, FontSource, Ui};
+use imgui::{
+    Condition, Context, FontConfig, FontGlyphRanges, FontSource, ImStr, ImString, TextureId, Ui,
+};
 use imgui_glium_renderer::Renderer;
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
 
 use mandelbrot::{
-    bounded::BoundsSettings,
-    compute::{Compute, ComputeEngine, ComputeSettings, ComputedSet},
+    bounded::{BailoutNorm, BoundsSettings},
+    buddhabrot::BuddhabrotSettings,
+    compute::{
+        CancelToken, Compute, ComputeEngine, ComputeSettings, ComputedSet, DensitySet,
+        LyapunovSettings, NewtonSet, Plane,
+    },
+    expression::Expression,
+    formula::FractalFormula,
+    layer::Layer,
+    newton::Polynomial,
+    tile::Tile,
+    trap::Trap,
+};
+
+use ui::{
+    events::ComputeEvent,
+    render::{AppRenderer, MakeTexture},
 };
 
-use ui::{events::ComputeEvent, render::AppRenderer};
+const PREVIEW_SIZE: [u32; 2] = [160, 90];
+// The preview only has to show the shape of the Julia set, it is rendered at
+// most this many iterations deep to keep up with the cursor.
+const PREVIEW_ITERATIONS: u64 = 1000;
+const FORMULA_CAPACITY: usize = 256;
+const DEFAULT_FORMULA: &str = "z^2 + c";
+const DEFAULT_POLYNOMIAL: &str = "z^3 - 1";
+const DEFAULT_STRIPE_DENSITY: f64 = 5.0;
+const DEFAULT_SEQUENCE: &str = "AABAB";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FractalMode {
+    EscapeTime,
+    Newton,
+    Buddhabrot,
+    Lyapunov,
+}
+
+impl FractalMode {
+    pub fn to_int(self) -> i32 {
+        match self {
+            Self::EscapeTime => 0,
+            Self::Newton => 1,
+            Self::Buddhabrot => 2,
+            Self::Lyapunov => 3,
+        }
+    }
+
+    pub fn from_int(value: i32) -> Self {
+        match value {
+            0 => Self::EscapeTime,
+            1 => Self::Newton,
+            2 => Self::Buddhabrot,
+            3 => Self::Lyapunov,
+            _ => Self::EscapeTime,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct AppSettings {
     precision: u32,
     resolution: [u32; 2],
     iterations: u64,
+    series_tolerance: f64,
+    escape_radius: f64,
+    bailout_norm: BailoutNorm,
+    trap: Option<Trap>,
+    stripe_density: Option<f64>,
+    mode: FractalMode,
     engine: ComputeEngine,
+    formula: FractalFormula,
+    formula_source: ImString,
+    expression: Result<Expression, String>,
+    julia: bool,
+    julia_c: [f64; 2],
+    julia_preview: bool,
+    focus_cursor: bool,
+    guessing: bool,
+    polynomial_source: ImString,
+    polynomial: Result<Polynomial, String>,
+    buddhabrot: BuddhabrotSettings,
+    sequence_source: ImString,
+    sequence: Result<Vec<bool>, String>,
+    warmup: u64,
 }
 
 impl AppSettings {
     pub fn new() -> AppSettings {
+        let mut formula_source = ImString::with_capacity(FORMULA_CAPACITY);
+        formula_source.push_str(DEFAULT_FORMULA);
+        let mut polynomial_source = ImString::with_capacity(FORMULA_CAPACITY);
+        polynomial_source.push_str(DEFAULT_POLYNOMIAL);
+        let mut sequence_source = ImString::with_capacity(FORMULA_CAPACITY);
+        sequence_source.push_str(DEFAULT_SEQUENCE);
         AppSettings {
             precision: 53,
             resolution: [1600, 900],
             iterations: 500,
+            series_tolerance: 1e-6,
+            escape_radius: 2.0,
+            bailout_norm: BailoutNorm::Modulus,
+            trap: None,
+            stripe_density: None,
+            mode: FractalMode::EscapeTime,
             engine: ComputeEngine::SimdF64x4,
+            formula: FractalFormula::Mandelbrot,
+            formula_source,
+            expression: Expression::parse(DEFAULT_FORMULA),
+            julia: false,
+            julia_c: [-0.8, 0.156],
+            julia_preview: true,
+            focus_cursor: false,
+            guessing: false,
+            polynomial_source,
+            polynomial: Polynomial::parse(DEFAULT_POLYNOMIAL),
+            buddhabrot: BuddhabrotSettings::new(10_000_000, [5000, 500, 50], true)
+                .with_seed(new_seed()),
+            sequence_source,
+            sequence: LyapunovSettings::parse_sequence(DEFAULT_SEQUENCE),
+            warmup: 100,
         }
     }
+
+    /// Selects a user formula, see `Expression::parse` for the syntax.
+    pub fn with_expression(mut self, source: &str) -> Result<AppSettings, String> {
+        self.expression = Ok(Expression::parse(source)?);
+        self.formula = FractalFormula::Custom;
+        self.formula_source.clear();
+        self.formula_source.push_str(source);
+        Ok(self)
+    }
+
+    /// Switches to a Newton fractal of `source`, see `Polynomial::parse`.
+    pub fn with_polynomial(mut self, source: &str) -> Result<AppSettings, String> {
+        self.polynomial = Ok(Polynomial::parse(source)?);
+        self.mode = FractalMode::Newton;
+        self.polynomial_source.clear();
+        self.polynomial_source.push_str(source);
+        Ok(self)
+    }
+
+    fn get_expression(&self) -> Option<Expression> {
+        match self.formula {
+            FractalFormula::Custom => self.expression.clone().ok(),
+            _ => None,
+        }
+    }
+
+    /// Precision of the view coordinates, raised to what the engine computes
+    /// with so zooming keeps up with the multi-double engines.
+    fn view_precision(&self) -> u32 {
+        self.precision.max(self.engine.precision())
+    }
 }
 
 #[derive(Clone)]
@@ -50,10 +183,43 @@ impl ZoomState {
     fn new(settings: &AppSettings) -> ZoomState {
         ZoomState {
             pos: [
-                Float::with_val(settings.precision, -0.5),
-                Float::with_val(settings.precision, 0.0),
+                Float::with_val(settings.view_precision(), -0.5),
+                Float::with_val(settings.view_precision(), 0.0),
             ],
-            scale: Float::with_val(settings.precision, 1.75),
+            scale: Float::with_val(settings.view_precision(), 1.75),
+        }
+    }
+
+    /// The region of `(a, b)` where the logistic map is interesting.
+    fn lyapunov(settings: &AppSettings) -> ZoomState {
+        ZoomState {
+            pos: [
+                Float::with_val(settings.view_precision(), 3.0),
+                Float::with_val(settings.view_precision(), 3.0),
+            ],
+            scale: Float::with_val(settings.view_precision(), 2.0),
+        }
+    }
+
+    /// The starting view of the selected mode.
+    fn initial(settings: &AppSettings) -> ZoomState {
+        match settings.mode {
+            FractalMode::EscapeTime if settings.julia => ZoomState::centered(settings),
+            FractalMode::Newton => ZoomState::centered(settings),
+            FractalMode::Lyapunov => ZoomState::lyapunov(settings),
+            _ => ZoomState::new(settings),
+        }
+    }
+
+    /// A view of the origin, where Julia sets and Newton fractals are
+    /// centered.
+    fn centered(settings: &AppSettings) -> ZoomState {
+        ZoomState {
+            pos: [
+                Float::with_val(settings.view_precision(), 0.0),
+                Float::with_val(settings.view_precision(), 0.0),
+            ],
+            scale: Float::with_val(settings.view_precision(), 3.0),
         }
     }
 
@@ -69,17 +235,28 @@ impl ZoomState {
         &self.scale
     }
 
+    /// Complex coordinate of a window position given as a fraction of its
+    /// size.
+    fn get_point(&self, pos: [f64; 2], settings: &AppSettings) -> [Float; 2] {
+        let ratio = f64::from(settings.resolution[0]) / f64::from(settings.resolution[1]);
+        [
+            &self.pos[0]
+                + Float::with_val(settings.view_precision(), pos[0] - 0.5) * &self.scale * ratio,
+            &self.pos[1] - Float::with_val(settings.view_precision(), pos[1] - 0.5) * &self.scale,
+        ]
+    }
+
     fn set_by_dragging(&mut self, start: [f64; 2], end: [f64; 2], settings: &AppSettings) {
         let scale_xy = [(start[0] - end[0]).abs(), (start[1] - end[1]).abs()];
         let ratio = f64::from(settings.resolution[0]) / f64::from(settings.resolution[1]);
-        let scale = Float::with_val(settings.precision, scale_xy[1]) * &self.scale;
+        let scale = Float::with_val(settings.view_precision(), scale_xy[1]) * &self.scale;
         let pos = [
             &self.pos[0]
-                + Float::with_val(settings.precision, (start[0] + end[0]) - 1.0) / 2.0
+                + Float::with_val(settings.view_precision(), (start[0] + end[0]) - 1.0) / 2.0
                     * &self.scale
                     * ratio,
             &self.pos[1]
-                - Float::with_val(settings.precision, (start[1] + end[1]) - 1.0) / 2.0
+                - Float::with_val(settings.view_precision(), (start[1] + end[1]) - 1.0) / 2.0
                     * &self.scale,
         ];
         self.pos = pos;
@@ -87,10 +264,21 @@ impl ZoomState {
     }
 }
 
+/// A finished render, in the output type of the mode it was made in.
+pub enum ComputedOutput {
+    Set(ComputedSet),
+    Newton(NewtonSet),
+    Density(DensitySet),
+}
+
 pub struct AppState {
-    pub computed_set: ComputedSet,
+    pub computed_set: ComputedOutput,
     pub set_valid: bool,
     pub progress: ComputeEvent,
+    pub glitches: u64,
+    pub julia_preview: Option<TextureId>,
+    pub preview_c: [f64; 2],
+    pub preview_cancel: CancelToken,
 
     pub mouse_pos: [f64; 2],
     pub dragging: bool,
@@ -99,22 +287,34 @@ pub struct AppState {
     pub zoomstate: ZoomState,
     pub compute_valid: bool,
     pub compute_busy: bool,
+    pub compute_cancel: CancelToken,
+    /// Tiles of the running render not yet drawn.
+    pub tiles: Vec<(Tile, ComputedSet)>,
+    /// Workers shared by the background computations.
+    pub thread_pool: ThreadPool,
 }
 
 impl AppState {
     fn new(settings: &AppSettings) -> AppState {
         AppState {
-            computed_set: ComputedSet::empty(64, 64),
+            computed_set: ComputedOutput::Set(ComputedSet::empty(64, 64)),
             set_valid: false,
             progress: ComputeEvent::End,
+            glitches: 0,
+            julia_preview: None,
+            preview_c: [0.0, 0.0],
+            preview_cancel: CancelToken::new(),
 
             mouse_pos: [0.0, 0.0],
             dragging: false,
             mouse_start: [0.0, 0.0],
             mouse_end: [0.0, 0.0],
-            zoomstate: ZoomState::new(settings),
+            zoomstate: ZoomState::initial(settings),
             compute_valid: false,
             compute_busy: false,
+            compute_cancel: CancelToken::new(),
+            tiles: Vec::new(),
+            thread_pool: ThreadPool::new(8),
         }
     }
 }
@@ -190,34 +390,157 @@ impl App {
         }
     }
 
+    /// `focus` is the position in the window, as a fraction of its size,
+    /// rendering starts around.
     fn recompute(
         zoomstate: &ZoomState,
         settings: &AppSettings,
-        tx: Sender<ComputedSet>,
-        update_tx: Sender<ComputeEvent>,
+        focus: Option<[f64; 2]>,
+        mut thread_pool: ThreadPool,
+        cancel: CancelToken,
+        tx: Sender<(CancelToken, ComputedOutput)>,
+        update_tx: Sender<(CancelToken, ComputeEvent)>,
     ) -> thread::JoinHandle<()> {
-        let prec = settings.precision;
+        let prec = settings.view_precision();
         let x = Float::with_val(prec, zoomstate.get_x());
         let y = Float::with_val(prec, zoomstate.get_y());
         let scale = Float::with_val(prec, zoomstate.get_scale());
         let [w, h] = settings.resolution;
         let engine = settings.engine;
         let iterations = settings.iterations;
+        let series_tolerance = settings.series_tolerance;
+        let escape_radius = settings.escape_radius;
+        let bailout_norm = settings.bailout_norm;
+        let trap = settings.trap;
+        let stripe_density = settings.stripe_density;
+        let formula = settings.formula;
+        let guessing = settings.guessing;
+        // Solid guessing trades the continuous layers for speed.
+        let layers = if guessing {
+            Some(vec![Layer::Iterations, Layer::Period])
+        } else {
+            None
+        };
+        let expression = settings.get_expression();
+        let mode = settings.mode;
+        let polynomial = settings.polynomial.clone();
+        let buddhabrot = settings.buddhabrot;
+        let lyapunov = settings
+            .sequence
+            .clone()
+            .map(|sequence| LyapunovSettings::new(sequence, settings.warmup, iterations));
+        let plane = if settings.julia {
+            let [cx, cy] = settings.julia_c;
+            Plane::Julia(Float::with_val(prec, cx), Float::with_val(prec, cy))
+        } else {
+            Plane::Mandelbrot
+        };
+        // The first image row is at the bottom of the window.
+        let focus = focus.map(|[x, y]| {
+            (
+                (x * f64::from(w)).max(0.0) as u32,
+                ((1.0 - y) * f64::from(h)).max(0.0) as u32,
+            )
+        });
         thread::spawn(move || {
-            tx.send(Compute::compute_set(
-                Some(&mut ThreadPool::new(8)),
-                Some(update_tx),
-                &ComputeSettings::new(
-                    x,
-                    y,
-                    scale,
-                    w,
-                    h,
-                    engine,
-                    BoundsSettings::new(iterations, prec),
+            let settings = ComputeSettings::new(
+                x,
+                y,
+                scale,
+                w,
+                h,
+                engine,
+                BoundsSettings::new(
+                    iterations,
+                    prec,
+                    series_tolerance,
+                    escape_radius,
+                    bailout_norm,
+                )
+                .with_trap(trap)
+                .with_stripe_density(stripe_density),
+            )
+            .with_plane(plane)
+            .with_formula(formula)
+            .with_expression(expression)
+            .with_cancel(cancel.clone())
+            .with_focus(focus)
+            .with_guessing(guessing)
+            .with_layers(layers);
+            let output = match (mode, polynomial, lyapunov) {
+                (FractalMode::Newton, Ok(polynomial), _) => {
+                    ComputedOutput::Newton(Compute::compute_newton(
+                        Some(&mut thread_pool),
+                        Some(update_tx),
+                        &settings,
+                        &polynomial,
+                    ))
+                }
+                (FractalMode::Buddhabrot, _, _) => {
+                    ComputedOutput::Density(Compute::compute_buddhabrot(
+                        Some(&mut thread_pool),
+                        Some(update_tx),
+                        &settings,
+                        buddhabrot,
+                    ))
+                }
+                (FractalMode::Lyapunov, _, Ok(lyapunov)) => {
+                    ComputedOutput::Set(Compute::compute_lyapunov(
+                        Some(&mut thread_pool),
+                        Some(update_tx),
+                        &settings,
+                        &lyapunov,
+                    ))
+                }
+                _ => ComputedOutput::Set(Compute::compute_set(
+                    Some(&mut thread_pool),
+                    Some(update_tx),
+                    &settings,
+                )),
+            };
+            tx.send((cancel, output)).unwrap();
+        })
+    }
+
+    fn recompute_preview(
+        c: [f64; 2],
+        settings: &AppSettings,
+        thread_pool: &ThreadPool,
+        cancel: CancelToken,
+        tx: Sender<(CancelToken, [f64; 2], ComputedSet)>,
+    ) {
+        let prec = settings.precision;
+        let iterations = settings.iterations.min(PREVIEW_ITERATIONS);
+        let series_tolerance = settings.series_tolerance;
+        let escape_radius = settings.escape_radius;
+        let bailout_norm = settings.bailout_norm;
+        let formula = settings.formula;
+        let expression = settings.get_expression();
+        thread_pool.execute(move || {
+            let settings = ComputeSettings::new(
+                Float::with_val(prec, 0.0),
+                Float::with_val(prec, 0.0),
+                Float::with_val(prec, 3.0),
+                PREVIEW_SIZE[0],
+                PREVIEW_SIZE[1],
+                ComputeEngine::SimdF64x4,
+                BoundsSettings::new(
+                    iterations,
+                    prec,
+                    series_tolerance,
+                    escape_radius,
+                    bailout_norm,
                 ),
+            )
+            .with_plane(Plane::Julia(
+                Float::with_val(prec, c[0]),
+                Float::with_val(prec, c[1]),
             ))
-            .unwrap();
+            .with_formula(formula)
+            .with_expression(expression)
+            .with_cancel(cancel.clone());
+            let set = Compute::compute_set(None, None, &settings);
+            tx.send((cancel, c, set)).unwrap();
         })
     }
 
@@ -227,6 +550,8 @@ impl App {
     ) {
         let (tx, rx) = channel();
         let (compute_tx, compute_rx) = channel();
+        let (preview_tx, preview_rx) = channel();
+        let mut preview_requested = None;
 
         let display = self.display.borrow();
         let gl_window = display.gl_window();
@@ -282,13 +607,11 @@ impl App {
                         if !imgui.io().want_capture_mouse {
                             match mouse_state {
                                 ElementState::Pressed => {
-                                    if !state.compute_busy {
-                                        state.mouse_start = state.mouse_pos;
-                                        state.dragging = true;
-                                    }
+                                    state.mouse_start = state.mouse_pos;
+                                    state.dragging = true;
                                 }
                                 ElementState::Released => {
-                                    if !state.compute_busy && state.dragging {
+                                    if state.dragging {
                                         state.mouse_end = state.mouse_pos;
                                         state.dragging = false;
                                         let start = state.mouse_start;
@@ -304,19 +627,96 @@ impl App {
                 }
             });
             if !state.compute_valid {
-                App::recompute(&state.zoomstate, &settings, tx.clone(), compute_tx.clone());
+                // A render that is still running is of an outdated view.
+                state.compute_cancel.cancel();
+                state.compute_cancel = CancelToken::new();
+                state.tiles.clear();
+                let focus = if settings.focus_cursor {
+                    Some(state.mouse_pos)
+                } else {
+                    None
+                };
+                App::recompute(
+                    &state.zoomstate,
+                    &settings,
+                    focus,
+                    state.thread_pool.clone(),
+                    state.compute_cancel.clone(),
+                    tx.clone(),
+                    compute_tx.clone(),
+                );
                 state.compute_valid = true;
                 state.compute_busy = true;
             }
 
-            if let Ok(result) = rx.try_recv() {
-                state.computed_set = result;
-                state.set_valid = false;
-                state.compute_busy = false;
+            for (cancel, result) in rx.try_iter() {
+                if !cancel.is_cancelled() {
+                    state.computed_set = result;
+                    state.set_valid = false;
+                    state.compute_busy = false;
+                }
             }
 
-            for event in compute_rx.try_iter() {
-                state.progress = event;
+            if settings.julia_preview && !settings.julia && settings.mode == FractalMode::EscapeTime
+            {
+                let c = state.zoomstate.get_point(state.mouse_pos, &settings);
+                let c = [c[0].to_f64(), c[1].to_f64()];
+                if preview_requested != Some(c) {
+                    // Only the preview at the latest position is shown.
+                    state.preview_cancel.cancel();
+                    state.preview_cancel = CancelToken::new();
+                    App::recompute_preview(
+                        c,
+                        &settings,
+                        &state.thread_pool,
+                        state.preview_cancel.clone(),
+                        preview_tx.clone(),
+                    );
+                    preview_requested = Some(c);
+                }
+            } else {
+                state.preview_cancel.cancel();
+                preview_requested = None;
+            }
+
+            for (cancel, c, result) in preview_rx.try_iter() {
+                if cancel.is_cancelled() {
+                    continue;
+                }
+                let texture = Rc::new(result.make_texture(&(*display)));
+                let mut imgui_render = imgui_render.borrow_mut();
+                let textures = imgui_render.textures();
+                state.julia_preview = Some(match state.julia_preview {
+                    Some(id) => {
+                        textures.replace(id, texture);
+                        id
+                    }
+                    None => textures.insert(texture),
+                });
+                state.preview_c = c;
+            }
+
+            for (cancel, event) in compute_rx.try_iter() {
+                // Events of a render that has been replaced since.
+                if cancel.is_cancelled() {
+                    continue;
+                }
+                match event {
+                    ComputeEvent::Glitches(glitches) => state.glitches = glitches,
+                    // A finished render is not replaced by a late preview.
+                    ComputeEvent::Pass(set) => {
+                        if state.compute_busy {
+                            state.computed_set = ComputedOutput::Set(set);
+                            state.set_valid = false;
+                        }
+                    }
+                    ComputeEvent::Tile(tile, set) => {
+                        if state.compute_busy {
+                            state.tiles.push((tile, set));
+                        }
+                    }
+                    event => state.progress = event,
+                }
             }
 
             let io = imgui.io_mut();
@@ -357,24 +757,227 @@ impl App {
                     ui.separator();
                     ui.text(im_str!("Scale:{:1})", state.zoomstate.get_scale()));
                     ui.separator();
-                    if ui.button(im_str!("Render"), [60.0, 20.0]) && !state.compute_busy {
+                    if ui.button(im_str!("Render"), [60.0, 20.0]) {
                         state.compute_valid = false;
                     };
+                    ui.checkbox(im_str!("Start at cursor"), &mut settings.focus_cursor);
+                    ui.checkbox(im_str!("Solid guessing"), &mut settings.guessing);
                     ui.separator();
                     let mut iterations = settings.iterations as i32;
                     ui.input_int(im_str!("Iterations"), &mut iterations).build();
-                    settings.iterations = iterations as u64;
+                    settings.iterations = iterations.max(1) as u64;
+                    let mut escape_radius = settings.escape_radius as f32;
+                    ui.input_float(im_str!("Escape radius"), &mut escape_radius)
+                        .build();
+                    settings.escape_radius = f64::from(escape_radius.max(2.0));
+                    let items = [
+                        im_str!("|z|"),
+                        im_str!("max(|re|, |im|)"),
+                        im_str!("|re|"),
+                        im_str!("|im|"),
+                        im_str!("re + im"),
+                    ];
+                    let mut select = settings.bailout_norm.to_int();
+                    if ui.list_box(im_str!("Bailout"), &mut select, &items, items.len() as i32) {
+                        settings.bailout_norm = BailoutNorm::from_int(select);
+                    }
+                    let items = [
+                        im_str!("None"),
+                        im_str!("Point"),
+                        im_str!("Line"),
+                        im_str!("Cross"),
+                        im_str!("Circle"),
+                        im_str!("Grid"),
+                    ];
+                    let mut select = settings.trap.map_or(0, |trap| trap.to_int() + 1);
+                    if ui.list_box(
+                        im_str!("Orbit trap"),
+                        &mut select,
+                        &items,
+                        items.len() as i32,
+                    ) {
+                        settings.trap = match select {
+                            0 => None,
+                            select => Some(Trap::from_int(select - 1)),
+                        };
+                    }
+                    if let Some(trap) = settings.trap {
+                        settings.trap = Some(trap_ui(ui, trap));
+                    }
+                    let mut averages = settings.stripe_density.is_some();
+                    if ui.checkbox(im_str!("Orbit averages"), &mut averages) {
+                        settings.stripe_density = if averages {
+                            Some(DEFAULT_STRIPE_DENSITY)
+                        } else {
+                            None
+                        };
+                    }
+                    if let Some(density) = settings.stripe_density {
+                        let density = input_value(ui, im_str!("Stripe density"), density);
+                        settings.stripe_density = Some(density);
+                    }
+                    ui.separator();
+                    let items = [
+                        im_str!("Escape time"),
+                        im_str!("Newton"),
+                        im_str!("Buddhabrot"),
+                        im_str!("Lyapunov"),
+                    ];
+                    let mut select = settings.mode.to_int();
+                    if ui.list_box(im_str!("Mode"), &mut select, &items, items.len() as i32) {
+                        let mode = FractalMode::from_int(select);
+                        if mode != settings.mode {
+                            settings.mode = mode;
+                            state.zoomstate = ZoomState::initial(settings);
+                            state.compute_valid = false;
+                        }
+                    }
+                    if settings.mode == FractalMode::Newton {
+                        if ui
+                            .input_text(im_str!("p(z)"), &mut settings.polynomial_source)
+                            .build()
+                        {
+                            settings.polynomial =
+                                Polynomial::parse(settings.polynomial_source.to_str());
+                        }
+                        if let Err(error) = &settings.polynomial {
+                            ui.text(im_str!("{}", error));
+                        }
+                    }
+                    if settings.mode == FractalMode::Buddhabrot {
+                        let mut samples = (settings.buddhabrot.samples / 1_000_000) as i32;
+                        ui.input_int(im_str!("Samples (million)"), &mut samples)
+                            .build();
+                        settings.buddhabrot.samples = samples.max(1) as u64 * 1_000_000;
+                        let limits = settings.buddhabrot.limits;
+                        let mut limits = [limits[0] as i32, limits[1] as i32, limits[2] as i32];
+                        ui.input_int3(im_str!("RGB limits"), &mut limits).build();
+                        settings.buddhabrot.limits = [
+                            limits[0].max(1) as u64,
+                            limits[1].max(1) as u64,
+                            limits[2].max(1) as u64,
+                        ];
+                        ui.checkbox(
+                            im_str!("Importance sampling"),
+                            &mut settings.buddhabrot.importance,
+                        );
+                        let mut seed = settings.buddhabrot.seed as i32;
+                        ui.input_int(im_str!("Seed"), &mut seed).build();
+                        settings.buddhabrot.seed = u64::from(seed as u32);
+                        if ui.button(im_str!("New seed"), [120.0, 20.0]) {
+                            settings.buddhabrot.seed = new_seed();
+                        }
+                    }
+                    if settings.mode == FractalMode::Lyapunov {
+                        if ui
+                            .input_text(im_str!("Sequence"), &mut settings.sequence_source)
+                            .build()
+                        {
+                            settings.sequence =
+                                LyapunovSettings::parse_sequence(settings.sequence_source.to_str());
+                        }
+                        if let Err(error) = &settings.sequence {
+                            ui.text(im_str!("{}", error));
+                        }
+                        let mut warmup = settings.warmup as i32;
+                        ui.input_int(im_str!("Warmup"), &mut warmup).build();
+                        settings.warmup = warmup.max(0) as u64;
+                    }
+                    ui.separator();
+                    ui.checkbox(im_str!("Julia"), &mut settings.julia);
+                    let mut julia_c = [settings.julia_c[0] as f32, settings.julia_c[1] as f32];
+                    if ui.input_float2(im_str!("Julia c"), &mut julia_c).build() {
+                        settings.julia_c = [f64::from(julia_c[0]), f64::from(julia_c[1])];
+                    }
+                    ui.checkbox(im_str!("Julia preview"), &mut settings.julia_preview);
+                    if let Some(texture) = state.julia_preview {
+                        if settings.julia_preview
+                            && !settings.julia
+                            && settings.mode == FractalMode::EscapeTime
+                        {
+                            let [w, h] = PREVIEW_SIZE;
+                            ui.image(texture, [w as f32, h as f32])
+                                .uv0([0.0, 1.0])
+                                .uv1([1.0, 0.0])
+                                .build();
+                            let [cx, cy] = state.preview_c;
+                            ui.text(im_str!("c: {:.6} {:+.6}i", cx, cy));
+                            if ui.button(im_str!("Use as Julia c"), [120.0, 20.0]) {
+                                settings.julia_c = state.preview_c;
+                                settings.julia = true;
+                                state.zoomstate = ZoomState::centered(settings);
+                                state.compute_valid = false;
+                            }
+                        }
+                    }
                     ui.separator();
                     let items = [
                         im_str!("Single"),
                         im_str!("Double"),
                         im_str!("Simd f64X4"),
+                        im_str!("DoubleDouble"),
+                        im_str!("Simd DoubleDouble X4"),
+                        im_str!("QuadDouble"),
                         im_str!("MPC"),
+                        im_str!("Perturbation"),
+                        im_str!("Perturbation FloatExp"),
                     ];
                     let mut select = settings.engine.to_int();
                     if ui.list_box(im_str!("Engine"), &mut select, &items, items.len() as i32) {
                         settings.engine = ComputeEngine::from_int(select);
                     }
+                    let engine = settings.engine.resolve(
+                        settings.formula,
+                        settings.julia,
+                        settings.get_expression().is_some(),
+                    );
+                    if engine.to_int() != select {
+                        ui.text(im_str!(
+                            "Computed with {}",
+                            items[engine.to_int() as usize].to_str()
+                        ));
+                    }
+                    let items = [
+                        im_str!("z^2 + c"),
+                        im_str!("Multibrot"),
+                        im_str!("Multibrot (real power)"),
+                        im_str!("Burning Ship"),
+                        im_str!("Tricorn"),
+                        im_str!("Celtic"),
+                        im_str!("Buffalo"),
+                        im_str!("Perpendicular"),
+                        im_str!("Custom"),
+                    ];
+                    let mut select = settings.formula.to_int();
+                    if ui.list_box(im_str!("Formula"), &mut select, &items, items.len() as i32) {
+                        settings.formula = FractalFormula::from_int(select);
+                    }
+                    match settings.formula {
+                        FractalFormula::Multibrot(power) => {
+                            let mut power = power as i32;
+                            ui.input_int(im_str!("Power"), &mut power).build();
+                            settings.formula = FractalFormula::Multibrot(power.max(2) as u32);
+                        }
+                        FractalFormula::MultibrotReal(power) => {
+                            let mut power = power as f32;
+                            ui.input_float(im_str!("Power"), &mut power).build();
+                            settings.formula =
+                                FractalFormula::MultibrotReal(f64::from(power.max(1.01)));
+                        }
+                        FractalFormula::Custom => {
+                            if ui
+                                .input_text(im_str!("z ="), &mut settings.formula_source)
+                                .build()
+                            {
+                                settings.expression =
+                                    Expression::parse(settings.formula_source.to_str());
+                            }
+                            if let Err(error) = &settings.expression {
+                                ui.text(im_str!("{}", error));
+                            }
+                        }
+                        _ => {}
+                    }
                     ui.separator();
                     let mut precision = settings.precision as i32;
                     ui.input_int(im_str!("MPC Precision"), &mut precision)
@@ -389,7 +992,52 @@ impl App {
                             ui.progress_bar(0f32).build();
                         }
                     }
+                    ui.text(im_str!("Glitched pixels: {}", state.glitches));
                 })
         });
     }
 }
+
+/// A Buddhabrot seed from the clock, kept to 32 bits so it can be edited.
+fn new_seed() -> u64 {
+    u64::from(precise_time_ns() as u32)
+}
+
+fn input_point(ui: &Ui, x: f64, y: f64) -> (f64, f64) {
+    let mut point = [x as f32, y as f32];
+    ui.input_float2(im_str!("Trap point"), &mut point).build();
+    (f64::from(point[0]), f64::from(point[1]))
+}
+
+fn input_value(ui: &Ui, label: &ImStr, value: f64) -> f64 {
+    let mut value = value as f32;
+    ui.input_float(label, &mut value).build();
+    f64::from(value)
+}
+
+/// Inputs for the parameters of an orbit trap.
+fn trap_ui(ui: &Ui, trap: Trap) -> Trap {
+    match trap {
+        Trap::Point(x, y) => {
+            let (x, y) = input_point(ui, x, y);
+            Trap::Point(x, y)
+        }
+        Trap::Line(x, y, angle) => {
+            let (x, y) = input_point(ui, x, y);
+            Trap::Line(x, y, input_value(ui, im_str!("Trap angle"), angle))
+        }
+        Trap::Cross(x, y) => {
+            let (x, y) = input_point(ui, x, y);
+            Trap::Cross(x, y)
+        }
+        Trap::Circle(x, y, radius) => {
+            let (x, y) = input_point(ui, x, y);
+            let radius = input_value(ui, im_str!("Trap radius"), radius);
+            Trap::Circle(x, y, radius.max(0.0))
+        }
+        Trap::Grid(spacing) => {
+            let spacing = input_value(ui, im_str!("Trap spacing"), spacing);
+            Trap::Grid(spacing.max(1e-3))
+        }
+    }
+}
@@ -1,6 +1,14 @@
+use mandelbrot::compute::ComputedSet;
+use mandelbrot::tile::Tile;
+
 #[derive(Clone)]
 pub enum ComputeEvent {
     Start,
     End,
     Progress((u32, u32)),
+    Glitches(u64),
+    /// A coarse pass of the image, scaled up to the full size.
+    Pass(ComputedSet),
+    /// A finished tile of the image, or of a pass of it.
+    Tile(Tile, ComputedSet),
 }
@@ -2,12 +2,17 @@ use glium::{
     backend::Facade,
     index::PrimitiveType,
     texture::{RawImage2d, Texture2d},
-    Surface,
+    Rect, Surface,
 };
 
-use mandelbrot::{bounded::Bound, compute::ComputedSet};
+use mandelbrot::{
+    compute::{ComputedSet, DensitySet, NewtonSet},
+    layer::BOUNDED,
+    newton::Basin,
+    tile::Tile,
+};
 
-use ui::app::AppState;
+use ui::app::{AppState, ComputedOutput};
 
 #[derive(Copy, Clone)]
 struct Vertex {
@@ -36,6 +41,11 @@ impl AppRenderer {
             self.computed_set_tex_cache = Some(state.computed_set.make_texture(facade));
             state.set_valid = true;
         }
+        if let Some(texture) = &self.computed_set_tex_cache {
+            for (tile, set) in state.tiles.drain(..) {
+                AppRenderer::write_tile(texture, tile, &set);
+            }
+        }
         AppRenderer::render_texture(
             self.computed_set_tex_cache.as_ref().unwrap(),
             target,
@@ -46,6 +56,26 @@ impl AppRenderer {
         }
     }
 
+    /// Draws a finished tile over the texture, tiles that do not fit it are
+    /// of a different resolution and skipped.
+    fn write_tile(texture: &Texture2d, tile: Tile, set: &ComputedSet) {
+        let fits = tile.x + tile.width <= texture.get_width()
+            && texture
+                .get_height()
+                .map_or(false, |height| tile.y + tile.height <= height);
+        if let (true, Some(data)) = (fits, set_colors(set)) {
+            texture.write(
+                Rect {
+                    left: tile.x,
+                    bottom: tile.y,
+                    width: tile.width,
+                    height: tile.height,
+                },
+                RawImage2d::from_raw_rgba(data, (tile.width, tile.height)),
+            );
+        }
+    }
+
     fn render_texture<T, F>(tex: &Texture2d, target: &mut T, facade: &F)
     where
         T: Surface,
@@ -208,6 +238,8 @@ impl AppRenderer {
     }
 }
 
+const TRAP_FALLOFF: f32 = 8.0;
+
 pub trait MakeTexture<F>
 where
     F: Facade,
@@ -215,25 +247,180 @@ where
     fn make_texture(&self, facade: &F) -> Texture2d;
 }
 
+/// RGBA values of every pixel of a set, `None` when it has no data.
+fn set_colors(set: &ComputedSet) -> Option<Vec<f32>> {
+    let layers = set.get_layers();
+    if let Some(exponents) = &layers.exponent {
+        Some(
+            exponents
+                .iter()
+                .flat_map(|&value| {
+                    let [r, g, b] = diverging(value);
+                    vec![r, g, b, 1.0]
+                })
+                .collect(),
+        )
+    } else if let Some(iterations) = &layers.iterations {
+        let value = |layer: &Option<Vec<f32>>, i: usize, default: f32| {
+            layer.as_ref().map_or(default, |layer| layer[i])
+        };
+        Some(
+            iterations
+                .iter()
+                .enumerate()
+                .flat_map(|(i, &iter)| {
+                    // Orbit traps light up the green channel.
+                    let trap = layers
+                        .trap
+                        .as_ref()
+                        .map_or(0.0, |traps| (-traps[i] * TRAP_FALLOFF).exp());
+                    if iter == BOUNDED {
+                        // Attracting cycles show the modulus of their
+                        // multiplier in blue.
+                        let multiplier = layers.multiplier.as_ref().map_or(0.0, |multipliers| {
+                            multipliers[i][0].hypot(multipliers[i][1])
+                        });
+                        return vec![0.0, trap, multiplier, 1.0];
+                    }
+                    // Escaping pixels show the stripe average in blue, shaded
+                    // by the triangle inequality average.
+                    let smooth = value(&layers.smooth, i, iter as f32);
+                    let stripe = value(&layers.stripe, i, 0.0);
+                    let shade = 0.5 + 0.5 * value(&layers.triangle, i, 1.0);
+                    vec![smooth / 500.0 * shade, trap * shade, stripe * shade, 1.0]
+                })
+                .collect(),
+        )
+    } else {
+        None
+    }
+}
+
 impl<F> MakeTexture<F> for ComputedSet
 where
     F: Facade,
 {
     fn make_texture(&self, facade: &F) -> Texture2d {
-        match self.iter() {
-            Some(data) => Texture2d::new(
-                facade,
-                RawImage2d::from_raw_rgba(
-                    data.flat_map(|bound| match bound {
-                        Bound::Bounded => vec![0.0, 0.0, 0.0, 1.0],
-                        Bound::Unbounded(n) => vec![*n as f32 / 500.0, 0.0, 0.0, 1.0],
+        let (width, height) = self.get_size();
+        match set_colors(self) {
+            Some(data) => {
+                Texture2d::new(facade, RawImage2d::from_raw_rgba(data, (width, height))).unwrap()
+            }
+            None => Texture2d::empty(facade, width, height).unwrap(),
+        }
+    }
+}
+
+// Ends of the diverging palette, white sits at zero.
+const NEGATIVE_COLOR: [f32; 3] = [0.23, 0.3, 0.75];
+const POSITIVE_COLOR: [f32; 3] = [0.7, 0.02, 0.15];
+
+/// Blue for negative values through white to red for positive ones, black
+/// for values that are not a number.
+fn diverging(value: f32) -> [f32; 3] {
+    if value.is_nan() {
+        return [0.0, 0.0, 0.0];
+    }
+    let t = value.tanh();
+    let end = if t < 0.0 {
+        NEGATIVE_COLOR
+    } else {
+        POSITIVE_COLOR
+    };
+    let t = t.abs();
+    [
+        1.0 + (end[0] - 1.0) * t,
+        1.0 + (end[1] - 1.0) * t,
+        1.0 + (end[2] - 1.0) * t,
+    ]
+}
+
+/// Fully saturated colour of `hue`, given as a fraction of the colour wheel.
+fn hue_to_rgb(hue: f32, value: f32) -> [f32; 3] {
+    let channel = |n: f32| {
+        let k = (n + hue * 6.0) % 6.0;
+        value * (1.0 - k.min(4.0 - k).min(1.0).max(0.0))
+    };
+    [channel(5.0), channel(3.0), channel(1.0)]
+}
+
+impl<F> MakeTexture<F> for NewtonSet
+where
+    F: Facade,
+{
+    fn make_texture(&self, facade: &F) -> Texture2d {
+        let roots = self.get_roots().len() as f32;
+        Texture2d::new(
+            facade,
+            RawImage2d::from_raw_rgba(
+                self.iter()
+                    .flat_map(|basin| match basin {
+                        Basin::Converged { root, iter } => {
+                            // Darken with the iterations so the basin
+                            // boundaries stand out.
+                            let value = 0.9f32.powf(*iter as f32).max(0.15);
+                            let [r, g, b] = hue_to_rgb(*root as f32 / roots, value);
+                            vec![r, g, b, 1.0]
+                        }
+                        Basin::Diverged => vec![0.0, 0.0, 0.0, 1.0],
                     })
                     .collect::<Vec<f32>>(),
-                    self.get_size(),
-                ),
-            )
-            .unwrap(),
-            None => Texture2d::empty(facade, self.get_size().0, self.get_size().1).unwrap(),
+                self.get_size(),
+            ),
+        )
+        .unwrap()
+    }
+}
+
+impl<F> MakeTexture<F> for DensitySet
+where
+    F: Facade,
+{
+    fn make_texture(&self, facade: &F) -> Texture2d {
+        let mut max = [0.0f32; 3];
+        for density in self.iter() {
+            for channel in 0..3 {
+                max[channel] = max[channel].max(density[channel]);
+            }
+        }
+        // The square root keeps the faint orbits visible next to the dense
+        // ones.
+        let scale = |density: f32, max: f32| {
+            if max > 0.0 {
+                (density / max).sqrt()
+            } else {
+                0.0
+            }
+        };
+        Texture2d::new(
+            facade,
+            RawImage2d::from_raw_rgba(
+                self.iter()
+                    .flat_map(|density| {
+                        vec![
+                            scale(density[0], max[0]),
+                            scale(density[1], max[1]),
+                            scale(density[2], max[2]),
+                            1.0,
+                        ]
+                    })
+                    .collect::<Vec<f32>>(),
+                self.get_size(),
+            ),
+        )
+        .unwrap()
+    }
+}
+
+impl<F> MakeTexture<F> for ComputedOutput
+where
+    F: Facade,
+{
+    fn make_texture(&self, facade: &F) -> Texture2d {
+        match self {
+            ComputedOutput::Set(set) => set.make_texture(facade),
+            ComputedOutput::Newton(set) => set.make_texture(facade),
+            ComputedOutput::Density(set) => set.make_texture(facade),
         }
     }
 }